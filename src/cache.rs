@@ -0,0 +1,172 @@
+use crate::error::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+// An on-disk cache of GET responses, keyed by url and the user making the request. Entries are
+// served straight from disk while they're younger than the TTL of their endpoint, and revalidated
+// with If-None-Match/If-Modified-Since once they're older. Only endpoints known to be read-only
+// are cached, since some of GOG's GET endpoints change things, like adding a tag.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    pub dir: PathBuf,
+    // Path prefixes of the endpoints that are cached. Nothing else ever goes through the cache.
+    pub endpoints: Vec<String>,
+    // TTLs by path prefix. The longest matching prefix wins.
+    pub ttls: Vec<(String, Duration)>,
+    // TTL for paths that don't match any prefix
+    pub default_ttl: Duration,
+    // Serve stale entries instead of failing when GOG can't be reached
+    pub offline: bool,
+}
+
+// A cached response body, along with the validators GOG sent with it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CacheEntry {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    // Seconds since the Unix epoch when the entry was last fetched or revalidated
+    pub stored_at: u64,
+    pub body: String,
+}
+
+impl Cache {
+    // Creates a cache in dir. Product data is kept for a day and game details for an hour, while
+    // everything else is revalidated on every request.
+    pub fn new(dir: impl Into<PathBuf>) -> Cache {
+        Cache {
+            dir: dir.into(),
+            endpoints: [
+                "/products",
+                "/account/gameDetails",
+                "/account/getFilteredProducts",
+                "/account/wishlist/search",
+                "/games/ajax/filtered",
+                "/public_wishlist/",
+                "/userData.json",
+                "/user/data/games",
+                "/user/wishlist.json",
+                "/user/games_rating.json",
+                "/user/review_votes.json",
+                "/users/info/",
+                "/v1/catalog",
+            ]
+            .iter()
+            .map(|x| x.to_string())
+            .collect(),
+            ttls: vec![
+                ("/products".to_string(), Duration::from_secs(24 * 60 * 60)),
+                (
+                    "/account/gameDetails".to_string(),
+                    Duration::from_secs(60 * 60),
+                ),
+            ],
+            default_ttl: Duration::from_secs(0),
+            offline: false,
+        }
+    }
+
+    // Caches the endpoints whose paths start with prefix. Only use this for endpoints that don't
+    // change anything.
+    pub fn endpoint(mut self, prefix: impl Into<String>) -> Cache {
+        self.endpoints.push(prefix.into());
+        self
+    }
+
+    // Sets the TTL of every path starting with prefix, replacing any earlier TTL for it
+    pub fn ttl(mut self, prefix: impl Into<String>, ttl: Duration) -> Cache {
+        let prefix = prefix.into();
+        self.ttls.retain(|x| x.0 != prefix);
+        self.ttls.push((prefix, ttl));
+        self
+    }
+
+    pub fn default_ttl(mut self, ttl: Duration) -> Cache {
+        self.default_ttl = ttl;
+        self
+    }
+
+    pub fn offline(mut self, offline: bool) -> Cache {
+        self.offline = offline;
+        self
+    }
+
+    // Whether responses from a path are cached
+    pub fn caches(&self, path: &str) -> bool {
+        self.endpoints.iter().any(|x| path.starts_with(x))
+    }
+
+    // The TTL that applies to a path
+    pub fn ttl_for(&self, path: &str) -> Duration {
+        self.ttls
+            .iter()
+            .filter(|x| path.starts_with(&x.0))
+            .max_by_key(|x| x.0.len())
+            .map(|x| x.1)
+            .unwrap_or(self.default_ttl)
+    }
+
+    // Looks up the entry for a url, if there is one. Unreadable entries are treated as missing.
+    pub fn get(&self, user_id: &str, url: &str) -> Option<CacheEntry> {
+        let text = fs::read_to_string(self.entry_path(user_id, url)).ok()?;
+        serde_json::from_str(&text).ok()
+    }
+
+    pub fn put(&self, user_id: &str, entry: &CacheEntry) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(
+            self.entry_path(user_id, &entry.url),
+            serde_json::to_string(entry)?,
+        )?;
+        Ok(())
+    }
+
+    // Removes every entry
+    pub fn clear(&self) -> Result<()> {
+        if self.dir.exists() {
+            fs::remove_dir_all(&self.dir)?;
+        }
+        Ok(())
+    }
+
+    fn entry_path(&self, user_id: &str, url: &str) -> PathBuf {
+        let key = md5::compute(format!("{}\n{}", user_id, url));
+        self.dir.join(format!("{:x}.json", key))
+    }
+}
+
+impl CacheEntry {
+    pub fn new(
+        url: impl Into<String>,
+        etag: Option<String>,
+        last_modified: Option<String>,
+        body: String,
+    ) -> CacheEntry {
+        CacheEntry {
+            url: url.into(),
+            etag,
+            last_modified,
+            stored_at: now(),
+            body,
+        }
+    }
+
+    // Whether the entry is younger than ttl
+    pub fn is_fresh(&self, ttl: Duration) -> bool {
+        now().saturating_sub(self.stored_at) < ttl.as_secs()
+    }
+
+    // Marks the entry as just revalidated
+    pub fn touch(&mut self) {
+        self.stored_at = now();
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("System time is before Unix Epoch")
+        .as_secs()
+}
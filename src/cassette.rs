@@ -0,0 +1,350 @@
+use crate::error::*;
+use crate::token::Token;
+use log::warn;
+use regex::Regex;
+use reqwest::blocking::{Client, Request, Response};
+use reqwest::header::{HeaderMap, CONTENT_TYPE, SET_COOKIE};
+use reqwest::{Body, ResponseBuilderExt, Url};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+// Recorded bodies that aren't JSON are cut short after this many bytes, so recording a download
+// doesn't store the whole file. Only the cassette is affected; the caller still gets the full
+// body, streamed as usual.
+pub const MAX_RECORDED_BODY: usize = 1024 * 1024;
+
+// Whether a cassette captures real traffic or serves it back
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CassetteMode {
+    Record,
+    Replay,
+}
+
+// A recorded request and the response to it. Tokens and emails are scrubbed before recording.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Interaction {
+    pub method: String,
+    pub url: String,
+    // The byte range requested, for partial downloads
+    pub range: Option<String>,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    // Whether body holds hex-encoded bytes, because the response wasn't valid UTF-8
+    #[serde(default)]
+    pub binary: bool,
+    // Whether body is only the start of the response, so it can't be replayed as a response
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+// The contents of a cassette file
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct CassetteFile {
+    // The user the interactions were recorded as
+    pub user_id: String,
+    pub interactions: Vec<Interaction>,
+}
+
+// Records the HTTP requests a Gog makes into a fixture file, or serves a recording back without
+// touching the network. Clones share the same recording, so workers can use it too.
+#[derive(Debug, Clone)]
+pub struct Cassette {
+    pub path: PathBuf,
+    pub mode: CassetteMode,
+    state: Arc<Mutex<CassetteState>>,
+}
+
+#[derive(Debug)]
+struct CassetteState {
+    file: CassetteFile,
+    // Which interactions have already been replayed
+    used: Vec<bool>,
+}
+
+impl Cassette {
+    // Starts a new recording for a user, replacing any recording already at path
+    pub fn record(path: impl Into<PathBuf>, user_id: impl Into<String>) -> Cassette {
+        Cassette::with_file(
+            path.into(),
+            CassetteMode::Record,
+            CassetteFile {
+                user_id: user_id.into(),
+                interactions: vec![],
+            },
+        )
+    }
+
+    // Loads a recording to serve back
+    pub fn replay(path: impl Into<PathBuf>) -> Result<Cassette> {
+        let path = path.into();
+        let file = serde_json::from_str(&fs::read_to_string(&path)?)?;
+        Ok(Cassette::with_file(path, CassetteMode::Replay, file))
+    }
+
+    fn with_file(path: PathBuf, mode: CassetteMode, file: CassetteFile) -> Cassette {
+        let used = vec![false; file.interactions.len()];
+        Cassette {
+            path,
+            mode,
+            state: Arc::new(Mutex::new(CassetteState { file, used })),
+        }
+    }
+
+    // A token for the recorded user that never expires, for building a Gog to replay with
+    pub fn token(&self) -> Token {
+        Token {
+            expires_in: u32::MAX as u64,
+            scope: String::new(),
+            token_type: "bearer".to_string(),
+            access_token: "REDACTED".to_string(),
+            user_id: self.state.lock().unwrap().file.user_id.clone(),
+            refresh_token: "REDACTED".to_string(),
+            session_id: "REDACTED".to_string(),
+            updated_at: 0,
+        }
+    }
+
+    pub fn interactions(&self) -> Vec<Interaction> {
+        self.state.lock().unwrap().file.interactions.clone()
+    }
+
+    // Sends a request, or replays the recorded response to it
+    pub(crate) fn send(&self, client: &Client, request: Request) -> Result<Response> {
+        let method = request.method().to_string();
+        let url = scrub(request.url().as_str());
+        if self.mode == CassetteMode::Replay {
+            return self.find(&method, &url, None)?.into_response();
+        }
+        let response = client.execute(request)?;
+        let mut builder = http::Response::builder()
+            .status(response.status())
+            .url(response.url().clone());
+        for (k, v) in response.headers().iter() {
+            builder = builder.header(k, v);
+        }
+        let json = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|x| x.to_str().ok())
+            .map(|x| x.contains("json"))
+            .unwrap_or(false);
+        let recorder = Recorder {
+            cassette: self.clone(),
+            pending: Some((
+                method,
+                url,
+                response.status().as_u16(),
+                response.headers().clone(),
+            )),
+            limit: if json { usize::MAX } else { MAX_RECORDED_BODY },
+            body: vec![],
+            cut: false,
+            ended: false,
+            response,
+        };
+        Ok(builder
+            .body(Body::wrap_stream(futures_util::stream::iter(recorder)))
+            .map_err(|e| Error::from(e.to_string()))?
+            .into())
+    }
+
+    // Fetches a byte range with fetch, or replays the recorded bytes
+    pub(crate) fn send_range<F>(&self, url: &str, start: i64, end: i64, fetch: F) -> Result<Vec<u8>>
+    where
+        F: FnOnce() -> Result<Vec<u8>>,
+    {
+        let url = scrub(url);
+        let range = format!("{}-{}", start, end);
+        if self.mode == CassetteMode::Replay {
+            return self.find("GET", &url, Some(&range))?.bytes();
+        }
+        let body = fetch()?;
+        self.push(Interaction::new(
+            "GET".to_string(),
+            url,
+            Some(range),
+            206,
+            &HeaderMap::new(),
+            &body,
+            false,
+        ))?;
+        Ok(body)
+    }
+
+    // Adds an interaction and saves the recording, so it survives a panicking test
+    fn push(&self, interaction: Interaction) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.file.interactions.push(interaction);
+        state.used.push(true);
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, serde_json::to_string_pretty(&state.file)?)?;
+        Ok(())
+    }
+
+    // Finds the first unused recording of a request. Once every recording of it has been
+    // replayed, the last one is served again.
+    fn find(&self, method: &str, url: &str, range: Option<&str>) -> Result<Interaction> {
+        let mut state = self.state.lock().unwrap();
+        let CassetteState { file, used } = &mut *state;
+        let matches =
+            |x: &Interaction| x.method == method && x.url == url && x.range.as_deref() == range;
+        let index = file
+            .interactions
+            .iter()
+            .zip(used.iter())
+            .position(|(x, used)| !used && matches(x))
+            .or_else(|| file.interactions.iter().rposition(matches));
+        match index {
+            Some(i) => {
+                used[i] = true;
+                Ok(file.interactions[i].clone())
+            }
+            None => Err(format!("no recorded response for {} {}", method, url).into()),
+        }
+    }
+}
+
+// Passes a response body on as it's read, keeping up to limit bytes of it for the cassette. The
+// interaction is recorded once the body ends or the response is dropped. If the response is
+// dropped before the end, what was read so far is recorded as truncated.
+struct Recorder {
+    cassette: Cassette,
+    // The method, url, status and headers of the response, until it's been recorded
+    pending: Option<(String, String, u16, HeaderMap)>,
+    limit: usize,
+    body: Vec<u8>,
+    // Whether bytes past the limit were left out
+    cut: bool,
+    ended: bool,
+    response: Response,
+}
+
+impl Recorder {
+    fn finish(&mut self) {
+        if let Some((method, url, status, headers)) = self.pending.take() {
+            let truncated = self.cut || !self.ended;
+            let interaction =
+                Interaction::new(method, url, None, status, &headers, &self.body, truncated);
+            if let Err(e) = self.cassette.push(interaction) {
+                warn!("Couldn't record a response: {}", e);
+            }
+            self.body = vec![];
+        }
+    }
+}
+
+impl Iterator for Recorder {
+    type Item = std::io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<std::io::Result<Vec<u8>>> {
+        let mut buffer = vec![0; 64 * 1024];
+        let read = match self.response.read(&mut buffer) {
+            Ok(0) => {
+                self.ended = true;
+                self.finish();
+                return None;
+            }
+            Ok(read) => read,
+            Err(e) => {
+                self.finish();
+                return Some(Err(e));
+            }
+        };
+        buffer.truncate(read);
+        let room = self.limit - self.body.len();
+        self.body.extend_from_slice(&buffer[..read.min(room)]);
+        self.cut |= read > room;
+        Some(Ok(buffer))
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+impl Interaction {
+    fn new(
+        method: String,
+        url: String,
+        range: Option<String>,
+        status: u16,
+        headers: &HeaderMap,
+        body: &[u8],
+        truncated: bool,
+    ) -> Interaction {
+        let headers = headers
+            .iter()
+            .filter(|(k, _)| *k != SET_COOKIE)
+            .map(|(k, v)| (k.to_string(), scrub(&String::from_utf8_lossy(v.as_bytes()))))
+            .collect();
+        let (body, binary) = match std::str::from_utf8(body) {
+            Ok(text) => (scrub(text), false),
+            Err(_) => (body.iter().map(|x| format!("{:02x}", x)).collect(), true),
+        };
+        Interaction {
+            method,
+            url,
+            range,
+            status,
+            headers,
+            body,
+            binary,
+            truncated,
+        }
+    }
+
+    // The raw body bytes
+    pub fn bytes(&self) -> Result<Vec<u8>> {
+        if !self.binary {
+            return Ok(self.body.clone().into_bytes());
+        }
+        (0..self.body.len())
+            .step_by(2)
+            .map(|i| {
+                self.body
+                    .get(i..i + 2)
+                    .and_then(|x| u8::from_str_radix(x, 16).ok())
+                    .ok_or_else(|| Error::from("invalid hex in recorded body"))
+            })
+            .collect()
+    }
+
+    fn into_response(self) -> Result<Response> {
+        if self.truncated {
+            return Err(format!(
+                "the recorded response to {} {} was cut short",
+                self.method, self.url
+            )
+            .into());
+        }
+        let url = Url::parse(&self.url).map_err(|e| Error::from(e.to_string()))?;
+        let mut builder = http::Response::builder().status(self.status).url(url);
+        for (k, v) in &self.headers {
+            builder = builder.header(k, v);
+        }
+        Ok(builder
+            .body(self.bytes()?)
+            .map_err(|e| Error::from(e.to_string()))?
+            .into())
+    }
+}
+
+// Replaces tokens, login codes and email addresses with placeholders
+pub fn scrub(text: &str) -> String {
+    let fields =
+        Regex::new(r#""(access_token|refresh_token|session_id|id_token)"(\s*):(\s*)"[^"]*""#)
+            .unwrap();
+    let params = Regex::new(r#"([?&](access_token|refresh_token|code|token)=)[^&"\s]*"#).unwrap();
+    let emails = Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}").unwrap();
+    let text = fields.replace_all(text, r#""$1"$2:$3"REDACTED""#);
+    let text = params.replace_all(&text, "${1}REDACTED");
+    emails.replace_all(&text, "user@example.com").to_string()
+}
@@ -0,0 +1,266 @@
+use crate::error::{ErrorKind::*, *};
+use crate::gog::*;
+use serde::{Deserialize, Serialize};
+use serde_json::value::{Map, Value};
+
+// Search criteria for the catalog API. Lists of slugs match products with any of them.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CatalogQuery {
+    pub search: Option<String>,
+    // Minimum and maximum price, in the query's currency
+    pub price_range: Option<(f64, f64)>,
+    pub discounted: bool,
+    pub release_statuses: Vec<ReleaseStatus>,
+    pub features: Vec<String>,
+    pub genres: Vec<String>,
+    pub tags: Vec<String>,
+    pub developers: Vec<String>,
+    pub publishers: Vec<String>,
+    // Language codes, like "en"
+    pub languages: Vec<String>,
+    pub systems: Vec<OS>,
+    pub order: Option<CatalogOrder>,
+    // ISO 3166 country code prices are shown for
+    pub country: Option<String>,
+    pub currency: Option<Currency>,
+    // Starts at 1
+    pub page: u32,
+    // Products per page. GOG allows at most 48.
+    pub limit: u32,
+}
+
+// Matches everything, starting from the first page with as many products per page as allowed
+impl Default for CatalogQuery {
+    fn default() -> CatalogQuery {
+        CatalogQuery {
+            search: None,
+            price_range: None,
+            discounted: false,
+            release_statuses: vec![],
+            features: vec![],
+            genres: vec![],
+            tags: vec![],
+            developers: vec![],
+            publishers: vec![],
+            languages: vec![],
+            systems: vec![],
+            order: None,
+            country: None,
+            currency: None,
+            page: 1,
+            limit: 48,
+        }
+    }
+}
+
+impl CatalogQuery {
+    pub fn new() -> CatalogQuery {
+        CatalogQuery::default()
+    }
+
+    // Creates a query that shows prices in the user's country and selected currency
+    pub fn for_user(user: &UserData) -> CatalogQuery {
+        CatalogQuery {
+            country: Some(user.country.clone()),
+            currency: Some(user.selected_currency.code),
+            ..CatalogQuery::new()
+        }
+    }
+
+    // Checks the query and turns it into request params
+    pub fn params(&self) -> Result<Map<String, Value>> {
+        let invalid = |reason: &str| Err(InvalidFilter(reason.to_string()).into());
+        if self.page < 1 {
+            return invalid("pages start at 1");
+        }
+        if self.limit < 1 || self.limit > 48 {
+            return invalid("limit must be between 1 and 48");
+        }
+        let mut params = Map::new();
+        let mut set = |key: &str, value: String| {
+            params.insert(key.to_string(), Value::String(value));
+        };
+        set("page", self.page.to_string());
+        set("limit", self.limit.to_string());
+        if let Some(search) = &self.search {
+            set("query", format!("like:{}", search));
+        }
+        if let Some((min, max)) = self.price_range {
+            if min > max {
+                return invalid("price range minimum is above the maximum");
+            }
+            set("price", format!("between:{},{}", min, max));
+        }
+        if self.discounted {
+            set("discounted", "eq:true".to_string());
+        }
+        let statuses: Vec<String> = self
+            .release_statuses
+            .iter()
+            .map(|x| x.as_str().to_string())
+            .collect();
+        let systems: Vec<String> = self
+            .systems
+            .iter()
+            .map(|x| {
+                match x {
+                    OS::Linux => "linux",
+                    OS::Windows => "windows",
+                    OS::MacOS => "osx",
+                }
+                .to_string()
+            })
+            .collect();
+        for (key, values) in [
+            ("releaseStatuses", &statuses),
+            ("features", &self.features),
+            ("genres", &self.genres),
+            ("tags", &self.tags),
+            ("developers", &self.developers),
+            ("publishers", &self.publishers),
+            ("languages", &self.languages),
+            ("systems", &systems),
+        ] {
+            if !values.is_empty() {
+                set(key, format!("in:{}", values.join(",")));
+            }
+        }
+        if let Some(order) = self.order {
+            set("order", order.as_str().to_string());
+        }
+        if let Some(country) = &self.country {
+            set("countryCode", country.clone());
+        }
+        if let Some(currency) = self.currency {
+            set("currencyCode", currency.to_string());
+        }
+        Ok(params)
+    }
+}
+
+// How catalog results are ordered
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+pub enum CatalogOrder {
+    Trending,
+    Bestselling,
+    Title,
+    // Newest first
+    ReleaseDate,
+    // Cheapest first
+    Price,
+    // Biggest discount first
+    Discount,
+    // Best reviewed first
+    Rating,
+}
+
+impl CatalogOrder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CatalogOrder::Trending => "desc:trending",
+            CatalogOrder::Bestselling => "desc:bestselling",
+            CatalogOrder::Title => "asc:title",
+            CatalogOrder::ReleaseDate => "desc:releaseDate",
+            CatalogOrder::Price => "asc:price",
+            CatalogOrder::Discount => "desc:discount",
+            CatalogOrder::Rating => "desc:reviewsRating",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReleaseStatus {
+    NewArrival,
+    Upcoming,
+    EarlyAccess,
+}
+
+impl ReleaseStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReleaseStatus::NewArrival => "new-arrival",
+            ReleaseStatus::Upcoming => "upcoming",
+            ReleaseStatus::EarlyAccess => "early-access",
+        }
+    }
+}
+
+// A page of catalog results
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CatalogPage {
+    pub pages: i64,
+    pub product_count: i64,
+    pub products: Vec<CatalogProduct>,
+}
+
+// A product in the catalog
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CatalogProduct {
+    pub id: String,
+    pub slug: String,
+    pub title: String,
+    pub product_type: String,
+    pub release_date: Option<String>,
+    pub store_release_date: Option<String>,
+    #[serde(default)]
+    pub developers: Vec<String>,
+    #[serde(default)]
+    pub publishers: Vec<String>,
+    #[serde(default)]
+    pub operating_systems: Vec<String>,
+    #[serde(default)]
+    pub features: Vec<CatalogLabel>,
+    #[serde(default)]
+    pub genres: Vec<CatalogLabel>,
+    #[serde(default)]
+    pub tags: Vec<CatalogLabel>,
+    pub price: Option<CatalogPrice>,
+    pub product_state: Option<String>,
+    // Average review score out of 50
+    pub reviews_rating: Option<i64>,
+    pub cover_horizontal: Option<String>,
+    pub cover_vertical: Option<String>,
+    pub store_link: Option<String>,
+}
+
+impl CatalogProduct {
+    // Parses the product id, which the catalog returns as a string
+    pub fn product_id(&self) -> Option<i64> {
+        self.id.parse().ok()
+    }
+
+    // Average review score out of 5
+    pub fn review_score(&self) -> Option<f32> {
+        self.reviews_rating.map(|x| x as f32 / 10.0)
+    }
+}
+
+// A feature, genre or tag
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CatalogLabel {
+    pub name: String,
+    pub slug: String,
+}
+
+// A price in the currency that was asked for
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CatalogPrice {
+    // Formatted amounts, like "$9.99"
+    #[serde(rename = "final")]
+    pub final_price: Option<String>,
+    pub base: Option<String>,
+    pub discount: Option<String>,
+    pub final_money: Money,
+    pub base_money: Money,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Money {
+    pub amount: String,
+    pub currency: String,
+    pub discount: Option<String>,
+}
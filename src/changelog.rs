@@ -0,0 +1,184 @@
+use crate::gog::parse_date;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use time::{Date, Month};
+
+// A single release in a changelog
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChangelogEntry {
+    // The heading of the entry, as plain text
+    pub title: String,
+    pub version: Option<String>,
+    pub date: Option<Date>,
+    pub changes: Vec<String>,
+}
+
+// Parses a GOG changelog from HTML. Headings that mention a version start a new entry, list items
+// and paragraphs under them become its changes. Entries are kept in the order GOG lists them,
+// which is usually newest first.
+pub fn parse_changelog(html: &str) -> Vec<ChangelogEntry> {
+    let items = Regex::new(r"(?i)<li[^>]*>").unwrap();
+    let breaks = Regex::new(r"(?i)<br\s*/?>|</(p|div|li|h[1-6]|ul|ol)>|<(h[1-6]|p)[^>]*>").unwrap();
+    let tags = Regex::new(r"<[^>]*>").unwrap();
+    let text = items.replace_all(html, "\n- ");
+    let text = breaks.replace_all(&text, "\n");
+    let text = decode_entities(&tags.replace_all(&text, ""));
+    let patterns = HeadingPatterns::new();
+    let mut entries: Vec<ChangelogEntry> = vec![];
+    for line in text.lines().map(|x| x.trim()).filter(|x| !x.is_empty()) {
+        if let Some(change) = line.strip_prefix("- ") {
+            push_change(&mut entries, change.trim());
+        } else if let Some(version) = patterns.find_version(line).filter(|_| line.len() < 100) {
+            entries.push(ChangelogEntry {
+                title: line.to_string(),
+                version: Some(version),
+                date: patterns.find_date(line),
+                changes: vec![],
+            });
+        } else {
+            push_change(&mut entries, line);
+        }
+    }
+    entries
+}
+
+fn push_change(entries: &mut Vec<ChangelogEntry>, change: &str) {
+    if entries.is_empty() {
+        entries.push(ChangelogEntry {
+            title: String::new(),
+            version: None,
+            date: None,
+            changes: vec![],
+        });
+    }
+    entries.last_mut().unwrap().changes.push(change.to_string());
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+// The patterns headings are searched with, compiled once per changelog
+struct HeadingPatterns {
+    version: Regex,
+    iso: Regex,
+    dotted: Regex,
+    written: Regex,
+}
+
+impl HeadingPatterns {
+    fn new() -> HeadingPatterns {
+        HeadingPatterns {
+            version: Regex::new(r"\b[vV]?(\d+(?:\.\d+)+[a-zA-Z0-9]*)\b").unwrap(),
+            iso: Regex::new(r"\d{4}-\d{2}-\d{2}").unwrap(),
+            dotted: Regex::new(r"(\d{1,2})\.(\d{1,2})\.(\d{4})").unwrap(),
+            written: Regex::new(r"(?i)([a-z]+)\.? (\d{1,2})(?:st|nd|rd|th)?,? (\d{4})").unwrap(),
+        }
+    }
+
+    fn find_version(&self, line: &str) -> Option<String> {
+        // Dates like 06.05.2020 look like versions, so they're skipped
+        let found = self
+            .version
+            .captures_iter(line)
+            .map(|x| x[1].to_string())
+            .find(|x| !is_dotted_date(x));
+        found
+    }
+
+    // Finds dates like 2020-05-06, 06.05.2020 or May 6, 2020
+    fn find_date(&self, line: &str) -> Option<Date> {
+        if let Some(date) = self.iso.find(line).and_then(|x| parse_date(x.as_str())) {
+            return Some(date);
+        }
+        if let Some(c) = self.dotted.captures(line) {
+            let month = Month::try_from(c[2].parse::<u8>().ok()?).ok()?;
+            return Date::from_calendar_date(c[3].parse().ok()?, month, c[1].parse().ok()?).ok();
+        }
+        for c in self.written.captures_iter(line) {
+            if let Some(month) = month_from_name(&c[1]) {
+                return Date::from_calendar_date(c[3].parse().ok()?, month, c[2].parse().ok()?)
+                    .ok();
+            }
+        }
+        None
+    }
+}
+
+fn is_dotted_date(version: &str) -> bool {
+    let parts: Vec<&str> = version.split('.').collect();
+    parts.len() == 3 && parts[0].len() <= 2 && parts[1].len() <= 2 && parts[2].len() == 4
+}
+
+fn month_from_name(name: &str) -> Option<Month> {
+    let months = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    let lower = name.to_lowercase();
+    let index = months.iter().position(|x| lower.starts_with(x))?;
+    Month::try_from(index as u8 + 1).ok()
+}
+
+// Compares versions component by component, numerically where possible
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let split = |x: &str| -> Vec<String> {
+        x.trim_start_matches(['v', 'V'])
+            .split(['.', '-', '_'])
+            .map(|x| x.to_string())
+            .collect()
+    };
+    let (a, b) = (split(a), split(b));
+    for i in 0..a.len().max(b.len()) {
+        let (x, y) = (a.get(i), b.get(i));
+        let ordering = match (x, y) {
+            (Some(x), Some(y)) => match (x.parse::<u64>(), y.parse::<u64>()) {
+                (Ok(x), Ok(y)) => x.cmp(&y),
+                _ => x.cmp(y),
+            },
+            (Some(_), None) => Ordering::Greater,
+            (None, Some(_)) => Ordering::Less,
+            (None, None) => Ordering::Equal,
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+// Gets the entries for versions newer than an installed version
+pub fn changes_since<'a>(
+    entries: &'a [ChangelogEntry],
+    installed: &str,
+) -> Vec<&'a ChangelogEntry> {
+    entries
+        .iter()
+        .filter(|x| {
+            x.version
+                .as_deref()
+                .map(|v| compare_versions(v, installed) == Ordering::Greater)
+                .unwrap_or(false)
+        })
+        .collect()
+}
+
+// Renders entries as markdown, with a heading per entry and a bullet per change
+pub fn to_markdown(entries: &[ChangelogEntry]) -> String {
+    let mut md = String::new();
+    for entry in entries {
+        if !entry.title.is_empty() {
+            md += &format!("## {}\n\n", entry.title);
+        }
+        for change in &entry.changes {
+            md += &format!("- {}\n", change);
+        }
+        md.push('\n');
+    }
+    md.trim_end().to_string() + "\n"
+}
@@ -24,6 +24,26 @@ error_chain! {
             description("not available")
             display("the resource requested is not available")
         }
+        IncompleteDownload(expected: u64, received: u64) {
+            description("download ended early")
+            display("download ended early: expected {} bytes, received {}", expected, received)
+        }
+        ChecksumMismatch(expected: String, received: String) {
+            description("checksum mismatch")
+            display("checksum mismatch: expected {}, received {}", expected, received)
+        }
+        InvalidFilter(reason: String) {
+            description("invalid filter")
+            display("invalid filter: {}", reason)
+        }
+        LoginTimeout {
+            description("login timed out")
+            display("timed out waiting for the login to finish")
+        }
+        LoginCancelled(reason: String) {
+            description("login cancelled")
+            display("login cancelled: {}", reason)
+        }
         IncorrectCredentials {
             description("the credentials provided were incorrect")
             display("the credentials provided were incorrect")
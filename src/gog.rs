@@ -1,8 +1,11 @@
+use crate::error::{ErrorKind::*, *};
+use domains::*;
 use serde::{Deserialize, Serialize};
 use serde_json::value::{Map, Value};
 use status::*;
 use std::collections::BTreeMap;
 use std::fmt;
+use time::{Date, Month};
 
 type GMap<K, V> = BTreeMap<K, V>;
 
@@ -19,9 +22,10 @@ pub mod domains {
     pub static EMBD: &str = "https://embed.gog.com";
     pub static AUTH: &str = "https://auth.gog.com";
     pub static BASE: &str = "https://gog.com";
+    pub static CATALOG: &str = "https://catalog.gog.com";
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
 pub enum OS {
     Linux,
     Windows,
@@ -40,7 +44,7 @@ impl OS {
 }
 
 // Available currencies
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
 pub enum Currency {
     USD,
     EUR,
@@ -134,11 +138,37 @@ pub mod status {
     #[derive(Serialize, Deserialize, Debug)]
     #[serde(rename_all = "camelCase")]
     pub struct WishlistStatus {
-        /// 0 is private, 1 is public, and 2 is for friends only
-        pub sharing: i32,
+        pub sharing: WishlistSharing,
         pub url: String,
     }
 
+    // Who can see a user's wishlist
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+    #[serde(try_from = "i32", into = "i32")]
+    pub enum WishlistSharing {
+        Private = 0,
+        Public = 1,
+        FriendsOnly = 2,
+    }
+
+    impl TryFrom<i32> for WishlistSharing {
+        type Error = String;
+        fn try_from(sharing: i32) -> Result<WishlistSharing, String> {
+            match sharing {
+                0 => Ok(WishlistSharing::Private),
+                1 => Ok(WishlistSharing::Public),
+                2 => Ok(WishlistSharing::FriendsOnly),
+                _ => Err(format!("unknown wishlist sharing {}", sharing)),
+            }
+        }
+    }
+
+    impl From<WishlistSharing> for i32 {
+        fn from(sharing: WishlistSharing) -> i32 {
+            sharing as i32
+        }
+    }
+
     #[derive(Serialize, Deserialize, Debug)]
     #[serde(rename_all = "camelCase")]
     pub struct BlockedStatus {
@@ -207,11 +237,63 @@ pub mod connect {
 
 // Things associated with the /products endpoinnt
 pub mod product {
+    use crate::changelog::*;
+    use crate::gog::GMap;
+    use serde::de::{DeserializeOwned, Deserializer, Error};
     use serde::{Deserialize, Serialize};
-    use serde_json::value::{Map, Value};
+    use serde_json::value::Value;
+    use std::fmt;
 
-    // The main product struct
-    #[derive(Serialize, Deserialize, Debug)]
+    // Optional parts of a product that the /products endpoint only returns when asked
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+    #[serde(rename_all = "snake_case")]
+    pub enum ProductExpand {
+        Downloads,
+        ExpandedDlcs,
+        Description,
+        Screenshots,
+        Videos,
+        RelatedProducts,
+        Changelog,
+    }
+
+    impl ProductExpand {
+        // Every expandable part
+        pub fn all() -> Vec<ProductExpand> {
+            use ProductExpand::*;
+            vec![
+                Downloads,
+                ExpandedDlcs,
+                Description,
+                Screenshots,
+                Videos,
+                RelatedProducts,
+                Changelog,
+            ]
+        }
+
+        pub fn as_str(&self) -> &'static str {
+            use ProductExpand::*;
+            match self {
+                Downloads => "downloads",
+                ExpandedDlcs => "expanded_dlcs",
+                Description => "description",
+                Screenshots => "screenshots",
+                Videos => "videos",
+                RelatedProducts => "related_products",
+                Changelog => "changelog",
+            }
+        }
+    }
+
+    impl fmt::Display for ProductExpand {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self.as_str())
+        }
+    }
+
+    // The main product struct. Fields that need a ProductExpand are None unless it was asked for.
+    #[derive(Serialize, Deserialize, Debug, Clone)]
     pub struct Product {
         pub id: i64,
         pub title: String,
@@ -219,7 +301,9 @@ pub mod product {
         pub purchase_link: Option<String>,
         pub slug: String,
         pub content_system_compatibility: OSSupport,
-        pub languages: Map<String, Value>,
+        // Language codes mapped to their names, like "en" to "English"
+        #[serde(default, deserialize_with = "empty_array_as_default")]
+        pub languages: GMap<String, String>,
         pub links: Links,
         pub in_development: InDev,
         pub is_secret: bool,
@@ -227,18 +311,67 @@ pub mod product {
         pub is_pre_order: bool,
         pub release_date: Option<String>,
         pub images: Images,
-        pub dlcs: Value,
+        // GOG sends an empty array when there are no DLCs
+        #[serde(default, deserialize_with = "empty_array_as_default")]
+        pub dlcs: Option<DlcList>,
         pub downloads: Option<DownObject>,
-        pub expanded_dlcs: Option<Vec<Value>>,
+        pub expanded_dlcs: Option<Vec<Product>>,
         pub description: Option<Description>,
         pub screenshots: Option<Vec<Screenshot>>,
-        pub videos: Option<Value>,
-        pub related_products: Option<Value>,
-        pub changelog: Option<Value>,
+        pub videos: Option<Vec<Video>>,
+        pub related_products: Option<Vec<Product>>,
+        // The changelog's HTML
+        pub changelog: Option<String>,
+    }
+
+    impl Product {
+        // Parses the changelog into entries, if it was expanded and the product has one
+        pub fn changelog_entries(&self) -> Vec<ChangelogEntry> {
+            self.changelog
+                .as_deref()
+                .map(parse_changelog)
+                .unwrap_or_default()
+        }
+    }
+
+    // GOG sends empty arrays in place of empty objects, so this treats those as missing
+    fn empty_array_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: DeserializeOwned + Default,
+    {
+        match Value::deserialize(deserializer)? {
+            Value::Null => Ok(T::default()),
+            Value::Array(array) if array.is_empty() => Ok(T::default()),
+            value => serde_json::from_value(value).map_err(D::Error::custom),
+        }
+    }
+
+    // Links to a product's DLCs
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct DlcList {
+        pub products: Vec<DlcLink>,
+        pub all_products_url: String,
+        pub expanded_all_products_url: String,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct DlcLink {
+        pub id: i64,
+        pub link: String,
+        pub expanded_link: String,
+    }
+
+    // A trailer or gameplay video
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    pub struct Video {
+        pub video_url: String,
+        pub thumbnail_url: String,
+        pub provider: String,
     }
 
     // What OS' this product supports
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Debug, Clone)]
     #[serde(rename_all = "camelCase")]
     pub struct OSSupport {
         pub windows: bool,
@@ -247,7 +380,7 @@ pub mod product {
     }
 
     // Various links to things related to a game
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Debug, Clone)]
     pub struct Links {
         pub purchase_link: String,
         pub product_card: String,
@@ -256,14 +389,14 @@ pub mod product {
     }
 
     // Whether or not a game is currently being developed
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Debug, Clone)]
     pub struct InDev {
         pub active: bool,
         pub until: Option<String>,
     }
 
     // Logos/Icons for a game
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Debug, Clone)]
     #[serde(rename_all = "camelCase")]
     pub struct Images {
         pub background: String,
@@ -274,13 +407,13 @@ pub mod product {
         pub sidebar_icon2x: String,
     }
 
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Debug, Clone)]
     pub struct DownObject {
         pub installers: Vec<Installer>,
     }
 
     // An installer & its info
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Debug, Clone)]
     pub struct Installer {
         pub id: String,
         pub name: String,
@@ -293,7 +426,7 @@ pub mod product {
     }
 
     // A specific downloadable file
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Debug, Clone)]
     pub struct File {
         pub id: String,
         pub size: i64,
@@ -301,7 +434,7 @@ pub mod product {
     }
 
     // Descriptions of a game
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Debug, Clone)]
     pub struct Description {
         pub lead: String,
         pub full: String,
@@ -309,14 +442,14 @@ pub mod product {
     }
 
     // A specific developer-provided screenshot
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Debug, Clone)]
     pub struct Screenshot {
         pub image_id: String,
         pub formatter_template_url: String,
         pub formatted_images: Vec<FormattedImage>,
     }
 
-    #[derive(Serialize, Deserialize, Debug)]
+    #[derive(Serialize, Deserialize, Debug, Clone)]
     pub struct FormattedImage {
         pub formatter_name: String,
         pub image_url: String,
@@ -384,7 +517,7 @@ pub struct LanguageInfo {
 }
 
 // The checksums of various user data
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 pub struct Checksum {
     pub cart: Option<String>,
     pub games: Option<String>,
@@ -441,6 +574,40 @@ pub struct GameDetails {
 }
 
 impl GameDetails {
+    // Parses the changelog into entries, if the game has one
+    pub fn changelog_entries(&self) -> Vec<crate::changelog::ChangelogEntry> {
+        self.changelog
+            .as_deref()
+            .map(crate::changelog::parse_changelog)
+            .unwrap_or_default()
+    }
+
+    // Parses this game's own CD keys, not including DLCs
+    pub fn cd_keys(&self) -> Vec<CdKey> {
+        self.cd_key
+            .as_deref()
+            .map(parse_cd_keys)
+            .unwrap_or_default()
+    }
+
+    // Approximate size in bytes of the builds for an OS in one language, including DLCs. The
+    // language is a code like "en", as returned by Download::language. Builds in other languages
+    // aren't counted, since only one of them would be installed.
+    pub fn total_size(&self, os: OS, language: &str) -> u64 {
+        self.downloads
+            .for_os(os)
+            .into_iter()
+            .flatten()
+            .filter(|x| x.language().as_deref() == Some(language))
+            .filter_map(|x| x.bytes())
+            .sum::<u64>()
+            + self
+                .dlcs
+                .iter()
+                .map(|x| x.total_size(os, language))
+                .sum::<u64>()
+    }
+
     pub fn all(self, linux: bool) -> Vec<Download> {
         let downloads = if linux {
             self.downloads.linux.unwrap()
@@ -470,12 +637,55 @@ impl GameDetails {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+// A CD key for third-party activation
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CdKey {
+    // What the key is for, like "Base game", if GOG labeled it
+    pub label: Option<String>,
+    pub key: String,
+}
+
+// Parses GameDetails' cd_key, which is either a bare key or HTML with several labeled keys
+pub fn parse_cd_keys(raw: &str) -> Vec<CdKey> {
+    let breaks = regex::Regex::new(r"(?i)<br\s*/?>|</(p|div|span|li)>").unwrap();
+    let tags = regex::Regex::new(r"<[^>]*>").unwrap();
+    let text = breaks.replace_all(raw, "\n");
+    let text = tags
+        .replace_all(&text, "")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&");
+    let mut keys = vec![];
+    let mut label = None;
+    for line in text.lines().map(|x| x.trim()).filter(|x| !x.is_empty()) {
+        if let Some(stripped) = line.strip_suffix(':') {
+            label = Some(stripped.trim().to_string());
+        } else if let Some((name, key)) = line.split_once(": ") {
+            keys.push(CdKey {
+                label: Some(name.trim().to_string()),
+                key: key.trim().to_string(),
+            });
+        } else {
+            keys.push(CdKey {
+                label: label.clone(),
+                key: line.to_string(),
+            });
+        }
+    }
+    keys
+}
+
+// Search criteria for get_filtered_products and get_products. Can be built up with the builder
+// methods, which replace any earlier param of the same kind. If no MediaType is given, games
+// (media type 1) are searched.
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct FilterParams {
     pub params: Vec<FilterParam>,
 }
 
 impl FilterParams {
+    pub fn new() -> FilterParams {
+        FilterParams::default()
+    }
     pub fn from_vec(p: Vec<FilterParam>) -> FilterParams {
         FilterParams { params: p }
     }
@@ -483,32 +693,216 @@ impl FilterParams {
         FilterParams { params: vec![p] }
     }
     pub fn to_query_string(&self) -> String {
-        let mut s = String::from("?");
-        for p in &self.params {
-            s = s + p.to_string().as_str() + "&";
+        encode_query(self.params.iter().map(|x| x.pair(FilterEndpoint::Account)))
+    }
+
+    // Checks the params against an endpoint, and returns them as a query string
+    pub fn query(&self, endpoint: FilterEndpoint) -> Result<String> {
+        self.validate(endpoint)?;
+        let mut pairs: Vec<(&str, String)> = self.params.iter().map(|x| x.pair(endpoint)).collect();
+        if !self
+            .params
+            .iter()
+            .any(|x| matches!(x, FilterParam::MediaType(_)))
+        {
+            pairs.push(("mediaType", "1".to_string()));
+        }
+        Ok(encode_query(pairs.into_iter()))
+    }
+
+    // Checks that every param is supported by an endpoint and has a sensible value
+    pub fn validate(&self, endpoint: FilterEndpoint) -> Result<()> {
+        use FilterParam::*;
+        let invalid = |reason: &str| Err(InvalidFilter(reason.to_string()).into());
+        for (i, param) in self.params.iter().enumerate() {
+            if self.params[..i]
+                .iter()
+                .any(|x| x.pair(endpoint).0 == param.pair(endpoint).0)
+            {
+                return invalid(&format!("{} given more than once", param.pair(endpoint).0));
+            }
+            if !param.supports(endpoint) {
+                return invalid(&format!("{:?} isn't supported by {:?}", param, endpoint));
+            }
+            match param {
+                Page(page) if *page < 1 => return invalid("pages start at 1"),
+                MediaType(media) if *media != 1 && *media != 2 => {
+                    return invalid("media type must be 1 (games) or 2 (movies)")
+                }
+                PriceRange(min, max) if min > max => {
+                    return invalid("price range minimum is above the maximum")
+                }
+                Search(st) if st.trim().is_empty() => return invalid("search is empty"),
+                _ => (),
+            }
         }
-        s.pop();
-        s
+        Ok(())
+    }
+
+    fn with(mut self, param: FilterParam) -> FilterParams {
+        self.params
+            .retain(|x| std::mem::discriminant(x) != std::mem::discriminant(&param));
+        self.params.push(param);
+        self
+    }
+
+    pub fn media_type(self, media_type: i32) -> FilterParams {
+        self.with(FilterParam::MediaType(media_type))
+    }
+    pub fn os(self, os: OS) -> FilterParams {
+        self.with(FilterParam::OS(os))
+    }
+    pub fn search(self, search: impl Into<String>) -> FilterParams {
+        self.with(FilterParam::Search(search.into()))
+    }
+    pub fn page(self, page: i32) -> FilterParams {
+        self.with(FilterParam::Page(page))
     }
+    pub fn sort(self, sort: SortOrder) -> FilterParams {
+        self.with(FilterParam::Sort(sort))
+    }
+    pub fn category(self, category: impl Into<String>) -> FilterParams {
+        self.with(FilterParam::Category(category.into()))
+    }
+    pub fn genre(self, genre: impl Into<String>) -> FilterParams {
+        self.with(FilterParam::Genre(genre.into()))
+    }
+    pub fn tag(self, tag_id: i64) -> FilterParams {
+        self.with(FilterParam::TagId(tag_id))
+    }
+    pub fn hidden(self, hidden: bool) -> FilterParams {
+        self.with(FilterParam::Hidden(hidden))
+    }
+    pub fn updated_only(self) -> FilterParams {
+        self.with(FilterParam::UpdatedOnly)
+    }
+    pub fn language(self, language: impl Into<String>) -> FilterParams {
+        self.with(FilterParam::ContentLanguage(language.into()))
+    }
+    pub fn price_range(self, min: u32, max: u32) -> FilterParams {
+        self.with(FilterParam::PriceRange(min, max))
+    }
+    pub fn discounted(self) -> FilterParams {
+        self.with(FilterParam::Discounted)
+    }
+}
+
+// Percent-encodes key/value pairs into a query string, including the leading '?'
+fn encode_query<K: AsRef<str>>(pairs: impl Iterator<Item = (K, String)>) -> String {
+    let mut url = reqwest::Url::parse(BASE).unwrap();
+    url.query_pairs_mut().extend_pairs(pairs);
+    format!("?{}", url.query().unwrap_or(""))
+}
+
+// The two endpoints FilterParams can be used with
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+pub enum FilterEndpoint {
+    // Products the user owns, used by get_filtered_products
+    Account,
+    // Products in the store, used by get_products
+    Store,
+    // Products on the user's wishlist, used by wishlist_products
+    Wishlist,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub enum FilterParam {
+    // 1 is games, 2 is movies
     MediaType(i32),
     OS(OS),
     Search(String),
     Page(i32),
+    Sort(SortOrder),
+    Category(String),
+    // Store only
+    Genre(String),
+    // Account only
+    TagId(i64),
+    // Account only. Whether to show hidden products instead of visible ones
+    Hidden(bool),
+    // Account only. Only shows products with updates
+    UpdatedOnly,
+    // A language code, like "en"
+    ContentLanguage(String),
+    // Store only. Minimum and maximum price, in whole units of the user's currency
+    PriceRange(u32, u32),
+    // Store only
+    Discounted,
+}
+
+impl FilterParam {
+    // Whether an endpoint understands this param
+    pub fn supports(&self, endpoint: FilterEndpoint) -> bool {
+        use FilterParam::*;
+        match self {
+            Genre(_) | PriceRange(..) | Discounted => endpoint == FilterEndpoint::Store,
+            // The wishlist search is like the account one, but without library-only filters
+            TagId(_) | Hidden(_) | UpdatedOnly => endpoint == FilterEndpoint::Account,
+            Sort(sort) => sort.supports(endpoint),
+            _ => true,
+        }
+    }
+
+    // The unencoded query key and value for an endpoint
+    pub fn pair(&self, endpoint: FilterEndpoint) -> (&'static str, String) {
+        use FilterParam::*;
+        match self {
+            MediaType(id) => ("mediaType", id.to_string()),
+            OS(os) => ("system", os.codes()),
+            Search(st) => ("search", st.clone()),
+            Page(num) => ("page", num.to_string()),
+            Sort(sort) => match endpoint {
+                FilterEndpoint::Store => ("sort", sort.as_str(endpoint).to_string()),
+                _ => ("sortBy", sort.as_str(endpoint).to_string()),
+            },
+            Category(category) => ("category", category.clone()),
+            Genre(genre) => ("genre", genre.clone()),
+            TagId(id) => ("tags", id.to_string()),
+            Hidden(hidden) => ("hiddenFlag", (*hidden as i32).to_string()),
+            UpdatedOnly => ("isUpdated", "1".to_string()),
+            ContentLanguage(lang) => ("language", lang.clone()),
+            PriceRange(min, max) => ("price", format!("{}to{}", min, max)),
+            Discounted => ("price", "discounted".to_string()),
+        }
+    }
 }
 
 impl fmt::Display for FilterParam {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        use FilterParam::*;
+        let (key, value) = self.pair(FilterEndpoint::Account);
+        write!(f, "{}", &encode_query(std::iter::once((key, value)))[1..])
+    }
+}
+
+// Orders results can be sorted in
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+pub enum SortOrder {
+    Title,
+    // Account only
+    DatePurchased,
+    // Wishlist only
+    DateAdded,
+    ReleaseDate,
+    Rating,
+}
+
+impl SortOrder {
+    pub fn supports(&self, endpoint: FilterEndpoint) -> bool {
         match self {
-            MediaType(id) => write!(f, "mediaType={}", id),
-            // OS filtering only for games, so forces games
-            OS(os) => write!(f, "system={}&mediaType=1", os.codes()),
-            Search(st) => write!(f, "search={}&mediaType=1", st),
-            Page(num) => write!(f, "page={}", num),
+            SortOrder::DatePurchased => endpoint == FilterEndpoint::Account,
+            SortOrder::DateAdded => endpoint == FilterEndpoint::Wishlist,
+            _ => true,
+        }
+    }
+
+    fn as_str(&self, endpoint: FilterEndpoint) -> &'static str {
+        match (self, endpoint) {
+            (SortOrder::Title, _) => "title",
+            (SortOrder::DatePurchased, _) => "date_purchased",
+            (SortOrder::DateAdded, _) => "date_added",
+            (SortOrder::ReleaseDate, FilterEndpoint::Store) => "date",
+            (SortOrder::ReleaseDate, _) => "release_date",
+            (SortOrder::Rating, _) => "rating",
         }
     }
 }
@@ -521,6 +915,22 @@ pub struct FilteredProducts {
     pub total_pages: i64,
     pub total_products: i64,
     pub products_per_page: i64,
+    // Every tag the user has created
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+}
+
+// A page of results from the store, returned from get_products_page
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FilteredUnownedProducts {
+    pub products: Vec<UnownedProductDetails>,
+    pub page: i64,
+    pub total_pages: i64,
+    #[serde(default)]
+    pub total_games_found: i64,
+    #[serde(default)]
+    pub total_movies_found: i64,
 }
 
 // Details of a product, returned from get_filtered_products
@@ -574,7 +984,7 @@ pub struct UnownedProductDetails {
     pub slug: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Price {
     pub amount: String,
@@ -614,7 +1024,7 @@ pub struct DurationEnd {
     pub timezone: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 #[serde(rename_all = "PascalCase")]
 pub struct WorksOn {
     pub windows: bool,
@@ -634,6 +1044,58 @@ pub struct Extra {
     pub size: String,
 }
 
+impl Extra {
+    // The kind of bonus content this is, parsed from its type
+    pub fn kind(&self) -> ExtraKind {
+        ExtraKind::from(self.r#type.as_str())
+    }
+
+    // Approximate size in bytes, parsed from the human-readable size
+    pub fn bytes(&self) -> Option<u64> {
+        parse_size(&self.size)
+    }
+}
+
+// Kinds of extras. Types GOG uses that don't fit any of these are kept as Other.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub enum ExtraKind {
+    Soundtrack,
+    Manual,
+    Artbook,
+    Wallpapers,
+    Avatars,
+    Video,
+    Patch,
+    GameAddon,
+    Other(String),
+}
+
+impl From<&str> for ExtraKind {
+    fn from(kind: &str) -> ExtraKind {
+        use ExtraKind::*;
+        let lower = kind.to_lowercase();
+        if lower.contains("soundtrack") || lower.contains("audio") {
+            Soundtrack
+        } else if lower.contains("manual") || lower.contains("guide") {
+            Manual
+        } else if lower.contains("artbook") || lower.contains("artwork") {
+            Artbook
+        } else if lower.contains("wallpaper") {
+            Wallpapers
+        } else if lower.contains("avatar") {
+            Avatars
+        } else if lower.contains("video") || lower.contains("trailer") {
+            Video
+        } else if lower.contains("patch") {
+            Patch
+        } else if lower.contains("add-on") || lower.contains("addon") {
+            GameAddon
+        } else {
+            Other(kind.to_string())
+        }
+    }
+}
+
 // A 'tag' on a game like Favorite
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -643,6 +1105,18 @@ pub struct Tag {
     pub product_count: String,
 }
 
+impl Tag {
+    // Parses the tag id, which GOG returns as a string
+    pub fn tag_id(&self) -> Option<i64> {
+        self.id.parse().ok()
+    }
+
+    // Parses how many products carry this tag
+    pub fn count(&self) -> Option<i64> {
+        self.product_count.parse().ok()
+    }
+}
+
 // A set of builds for a game for different OSes
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Downloads {
@@ -651,6 +1125,17 @@ pub struct Downloads {
     pub linux: Option<Vec<Download>>,
 }
 
+impl Downloads {
+    // Gets the builds for a single OS
+    pub fn for_os(&self, os: OS) -> Option<&Vec<Download>> {
+        match os {
+            OS::Linux => self.linux.as_ref(),
+            OS::Windows => self.windows.as_ref(),
+            OS::MacOS => self.mac.as_ref(),
+        }
+    }
+}
+
 // Information on an available build of a game
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -663,6 +1148,61 @@ pub struct Download {
     pub size: String,
 }
 
+impl Download {
+    // The language code of this build, taken from its manual url. For example, this is "en" for
+    // "/downloads/beneath_a_steel_sky/en1installer0".
+    pub fn language(&self) -> Option<String> {
+        let last = self.manual_url.rsplit('/').next()?;
+        let lang: String = last
+            .chars()
+            .take_while(|x| x.is_ascii_alphabetic())
+            .collect();
+        if lang.is_empty() {
+            None
+        } else {
+            Some(lang)
+        }
+    }
+
+    // Approximate size in bytes, parsed from the human-readable size. Use Gog::download_size for
+    // the exact size.
+    pub fn bytes(&self) -> Option<u64> {
+        parse_size(&self.size)
+    }
+
+    // The date of this build, if GOG provided one
+    pub fn parsed_date(&self) -> Option<Date> {
+        parse_date(&self.date)
+    }
+}
+
+// Parses sizes like "1.2 GB" into bytes. GOG uses binary units.
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let split = size
+        .find(|x: char| !(x.is_ascii_digit() || x == '.'))
+        .unwrap_or(size.len());
+    let amount: f64 = size[..split].parse().ok()?;
+    let multiplier: u64 = match size[split..].trim().to_uppercase().as_str() {
+        "" | "B" => 1,
+        "KB" => 1 << 10,
+        "MB" => 1 << 20,
+        "GB" => 1 << 30,
+        "TB" => 1 << 40,
+        _ => return None,
+    };
+    Some((amount * multiplier as f64).round() as u64)
+}
+
+// Parses dates starting with YYYY-MM-DD, ignoring anything after the day
+pub fn parse_date(date: &str) -> Option<Date> {
+    let mut parts = date.get(..10)?.split('-');
+    let year = parts.next()?.parse().ok()?;
+    let month = Month::try_from(parts.next()?.parse::<u8>().ok()?).ok()?;
+    let day = parts.next()?.parse().ok()?;
+    Date::from_calendar_date(year, month, day).ok()
+}
+
 // A user's wishlist
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Wishlist {
@@ -671,6 +1211,47 @@ pub struct Wishlist {
     pub checksum: String,
 }
 
+impl Wishlist {
+    // The game ids of wishlisted games
+    pub fn ids(&self) -> Vec<i64> {
+        self.wishlist
+            .iter()
+            .filter(|x| *x.1)
+            .filter_map(|x| x.0.parse().ok())
+            .collect()
+    }
+}
+
+// A page of wishlisted products, returned from wishlist_products_iter
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WishlistProducts {
+    pub products: Vec<WishlistProduct>,
+    pub page: i64,
+    pub total_pages: i64,
+    pub total_products: i64,
+}
+
+// A wishlisted product
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WishlistProduct {
+    pub id: i64,
+    pub title: String,
+    pub slug: String,
+    pub url: String,
+    pub image: String,
+    pub price: Option<Price>,
+    pub works_on: WorksOn,
+    #[serde(default)]
+    pub is_discounted: bool,
+    #[serde(default)]
+    pub is_coming_soon: bool,
+    // Unix timestamp of when the product was wishlisted
+    #[serde(default)]
+    pub date_added: Option<i64>,
+}
+
 // A list of achivements from GOG
 #[derive(Serialize, Deserialize, Debug)]
 pub struct AchievementList {
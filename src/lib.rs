@@ -2,6 +2,14 @@
 //! Many thanks to [Yepoleb](https://github.com/Yepoleb), who made
 //! [this](https://gogapidocs.readthedocs.io/en/latest/index.html) very helpful set of docs.
 use serde_json::json;
+/// Module for caching responses on disk
+pub mod cache;
+/// Module for recording and replaying HTTP traffic
+pub mod cassette;
+/// Module for searching the store through the catalog API
+pub mod catalog;
+/// Module for parsing game changelogs
+pub mod changelog;
 mod containers;
 /// Provides error-handling logic
 mod error;
@@ -9,8 +17,31 @@ mod error;
 pub mod extract;
 /// Module for GOG structs and responses
 pub mod gog;
+/// Module for fetching a user's whole library at once
+pub mod library;
+/// Module for mirroring a user's library to disk
+pub mod mirror;
+/// Module for the user's order history
+pub mod orders;
+/// Module for lazily paging through filtered products
+pub mod pages;
+mod pool;
+/// Module for regional prices and price history
+pub mod prices;
+/// Module for incrementally syncing a library using user data checksums
+pub mod sync;
+/// Module for syncing tags to a desired state
+pub mod tags;
+/// Module with a fake GOG server for tests
+#[cfg(feature = "testing")]
+pub mod testing;
 /// Module for OAuth token management
 pub mod token;
+/// Module for wishlist tools
+pub mod wishlist;
+use cache::*;
+use cassette::Cassette;
+use catalog::*;
 use connect::*;
 use containers::*;
 use curl::easy::{Easy2, Handler, WriteError};
@@ -21,21 +52,32 @@ pub use error::ErrorKind;
 pub use error::Result;
 use extract::*;
 use gog::*;
+use library::*;
+use log::warn;
+use orders::*;
+use pages::*;
+use prices::*;
 use product::*;
 use regex::*;
-use reqwest::blocking::{Client, Response};
+use reqwest::blocking::{Client, Request, Response};
 use reqwest::header::*;
 use reqwest::redirect::Policy;
-use reqwest::Method;
+use reqwest::{Method, StatusCode};
 use serde::de::DeserializeOwned;
 use serde_json::value::{Map, Value};
+use status::WishlistSharing;
 use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::io::BufRead;
 use std::io::BufReader;
 use std::io::Read;
 use token::Token;
+use wishlist::*;
 use ErrorKind::*;
 
+// The most ids the /products endpoint accepts in one request
+pub const PRODUCT_ID_LIMIT: usize = 50;
+
 const GET: Method = Method::GET;
 const POST: Method = Method::POST;
 
@@ -53,6 +95,12 @@ pub struct Gog {
     pub client: RefCell<Client>,
     pub client_noredirect: RefCell<Client>,
     pub auto_update: bool,
+    // Opt-in cache for GET responses
+    pub cache: Option<Cache>,
+    // Records or replays every request made
+    pub cassette: Option<Cassette>,
+    // Sends every request here instead of to GOG's domains, such as to a fake server in tests
+    pub base_url: Option<String>,
 }
 impl Gog {
     // Initializes out of a token from a login code
@@ -76,6 +124,9 @@ impl Gog {
             client: RefCell::new(client.build().unwrap()),
             client_noredirect: RefCell::new(client_re.build().unwrap()),
             auto_update: true,
+            cache: None,
+            cassette: None,
+            base_url: None,
         }
     }
 
@@ -90,6 +141,39 @@ impl Gog {
         self.token.replace(token);
     }
 
+    // Enables caching of GET responses
+    pub fn with_cache(mut self, cache: Cache) -> Gog {
+        self.cache = Some(cache);
+        self
+    }
+
+    // Records requests into, or replays them from, a cassette
+    pub fn with_cassette(mut self, cassette: Cassette) -> Gog {
+        self.cassette = Some(cassette);
+        self
+    }
+
+    // Sends every request to base_url instead of GOG
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Gog {
+        self.base_url = Some(base_url.into());
+        self
+    }
+
+    // A new Gog with the same settings, for use on another thread
+    pub(crate) fn fork(&self, token: Token) -> Gog {
+        let mut gog = Gog::new(token);
+        gog.auto_update = self.auto_update;
+        gog.cache = self.cache.clone();
+        gog.cassette = self.cassette.clone();
+        gog.base_url = self.base_url.clone();
+        gog
+    }
+
+    // The url to send requests meant for a GOG domain to
+    fn domain<'a>(&'a self, domain: &'a str) -> &'a str {
+        self.base_url.as_deref().unwrap_or(domain)
+    }
+
     pub fn uid_string(&self) -> String {
         self.token.borrow().user_id.clone()
     }
@@ -98,6 +182,18 @@ impl Gog {
         self.token.borrow().user_id.parse().unwrap()
     }
 
+    // Returns a valid token, refreshing the current one first if needed
+    fn fresh_token(&self) -> Result<Token> {
+        if self.token.borrow().is_expired() {
+            if !self.auto_update {
+                return Err(ExpiredToken.into());
+            }
+            let new_token = self.token.borrow().refresh_from(self.domain(AUTH))?;
+            self.update_token(new_token);
+        }
+        Ok(self.token.borrow().clone())
+    }
+
     fn headers_token(at: &str) -> reqwest::header::HeaderMap {
         let mut headers = reqwest::header::HeaderMap::new();
         headers.insert(
@@ -109,6 +205,14 @@ impl Gog {
         headers
     }
 
+    // Sends a request with client, through the cassette if there is one
+    fn send(&self, client: &Client, request: Request) -> Result<Response> {
+        match &self.cassette {
+            Some(cassette) => cassette.send(client, request),
+            None => Ok(client.execute(request)?),
+        }
+    }
+
     fn rget(
         &self,
         domain: &str,
@@ -136,25 +240,16 @@ impl Gog {
     ) -> Result<Response> {
         if self.token.borrow().is_expired() {
             if self.auto_update {
-                let new_token = self.token.borrow().refresh()?;
+                let new_token = self.token.borrow().refresh_from(self.domain(AUTH))?;
                 self.update_token(new_token);
                 self.rreq(method, domain, path, params)
             } else {
                 Err(ExpiredToken.into())
             }
         } else {
-            let mut url = domain.to_string() + path;
-            if let Some(temp_params) = params {
-                let params = temp_params;
-                if !params.is_empty() {
-                    url += "?";
-                    for (k, v) in params.iter() {
-                        url = url + k + "=" + &v.to_string() + "&";
-                    }
-                    url.pop();
-                }
-            }
-            Ok(self.client.borrow().request(method, &url).send()?)
+            let url = request_url(self.domain(domain), path, params)?;
+            let client = self.client.borrow();
+            self.send(&client, client.request(method, url).build()?)
         }
     }
 
@@ -182,9 +277,80 @@ impl Gog {
     where
         T: DeserializeOwned,
     {
-        let res = self.rreq(method, domain, path, params)?;
-        let st = res.text()?;
-        Ok(serde_json::from_str(&st)?)
+        // A failed request is reported by its status, unless its body is still what was asked for
+        let (failure, st) = match &self.cache {
+            Some(cache) if method == GET && cache.caches(path) => {
+                self.cached_get(cache, domain, path, params)?
+            }
+            _ => {
+                let res = self.rreq(method, domain, path, params)?;
+                (res.error_for_status_ref().err(), res.text()?)
+            }
+        };
+        serde_json::from_str(&st).map_err(|e| match failure {
+            Some(failure) => failure.into(),
+            None => e.into(),
+        })
+    }
+
+    // Gets a response body through the cache, revalidating stale entries, along with the error
+    // for a failed status. Only successful responses are stored.
+    fn cached_get(
+        &self,
+        cache: &Cache,
+        domain: &str,
+        path: &str,
+        params: Option<Map<String, Value>>,
+    ) -> Result<(Option<reqwest::Error>, String)> {
+        let url = request_url(self.domain(domain), path, params)?;
+        let uid = self.uid_string();
+        let entry = cache.get(&uid, url.as_str());
+        if let Some(entry) = &entry {
+            if entry.is_fresh(cache.ttl_for(path)) {
+                return Ok((None, entry.body.clone()));
+            }
+        }
+        let res = self.fresh_token().and_then(|_| {
+            let client = self.client.borrow();
+            let mut req = client.get(url.clone());
+            if let Some(entry) = &entry {
+                if let Some(etag) = &entry.etag {
+                    req = req.header(IF_NONE_MATCH, etag);
+                }
+                if let Some(modified) = &entry.last_modified {
+                    req = req.header(IF_MODIFIED_SINCE, modified);
+                }
+            }
+            self.send(&client, req.build()?)
+        });
+        let res = match (res, entry) {
+            (Ok(res), Some(mut entry)) if res.status() == StatusCode::NOT_MODIFIED => {
+                entry.touch();
+                cache.put(&uid, &entry)?;
+                return Ok((None, entry.body));
+            }
+            (Err(Error(Network(e), _)), Some(entry)) if cache.offline => {
+                warn!("Serving stale {}: {}", entry.url, e);
+                return Ok((None, entry.body));
+            }
+            (res, _) => res?,
+        };
+        let header = |name| {
+            res.headers()
+                .get(name)
+                .and_then(|x: &HeaderValue| x.to_str().ok())
+                .map(|x| x.to_string())
+        };
+        let (etag, last_modified) = (header(ETAG), header(LAST_MODIFIED));
+        let failure = res.error_for_status_ref().err();
+        let body = res.text()?;
+        if failure.is_none() {
+            cache.put(
+                &uid,
+                &CacheEntry::new(url.as_str(), etag, last_modified, body.clone()),
+            )?;
+        }
+        Ok((failure, body))
     }
 
     fn nfreq<T>(
@@ -243,7 +409,7 @@ impl Gog {
             EMBD,
             &("/users/info/".to_string() + &uid.to_string()),
             map_p!({
-            "expand": expand.iter().fold("".to_string(), fold_mult)
+            "expand": expand
             }),
         )
     }
@@ -275,39 +441,55 @@ impl Gog {
     pub fn download_game(&self, downloads: Vec<Download>) -> Vec<Result<Response>> {
         downloads
             .iter()
-            .map(|x| {
-                let mut url = BASE.to_string() + &x.manual_url;
-                let mut response;
-                loop {
-                    let temp_response = self.client_noredirect.borrow().get(url).send();
-                    if let Ok(temp) = temp_response {
-                        response = temp;
-                        let headers = response.headers();
-                        // GOG appears to be inconsistent with returning either 301/302, so this just checks for a redirect location.
-                        if headers.contains_key("location") {
-                            url = headers
-                                .get("location")
-                                .unwrap()
-                                .to_str()
-                                .unwrap()
-                                .to_string();
-                        } else {
-                            break;
-                        }
-                    } else {
-                        return Err(temp_response.err().unwrap().into());
-                    }
-                }
-                Ok(response)
-            })
+            .map(|x| self.follow_download(&x.manual_url))
+            .collect()
+    }
+
+    // Returns a vec of responses for extras, like soundtracks or manuals
+    pub fn download_extras(&self, extras: Vec<Extra>) -> Vec<Result<Response>> {
+        extras
+            .iter()
+            .map(|x| self.follow_download(&x.manual_url))
             .collect()
     }
 
+    // Gets the exact size in bytes of a download or extra, without downloading it
+    pub fn download_size(&self, manual_url: &str) -> Result<u64> {
+        // reqwest reports the length of the (empty) body for HEAD requests, so this reads the
+        // header instead.
+        self.follow_download_with(Method::HEAD, manual_url)?
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| x.parse().ok())
+            .ok_or_else(|| MissingField("content-length".to_string()).into())
+    }
+
+    // Follows GOG's redirects for a manual_url, returning the response for the file itself
+    fn follow_download(&self, manual_url: &str) -> Result<Response> {
+        self.follow_download_with(GET, manual_url)
+    }
+
+    fn follow_download_with(&self, method: Method, manual_url: &str) -> Result<Response> {
+        self.fresh_token()?;
+        let mut url = self.domain(BASE).to_string() + manual_url;
+        loop {
+            let client = self.client_noredirect.borrow();
+            let response = self.send(&client, client.request(method.clone(), &url).build()?)?;
+            // GOG appears to be inconsistent with returning either 301/302, so this just checks for a redirect location.
+            if let Some(location) = response.headers().get("location") {
+                url = location.to_str().unwrap().to_string();
+            } else {
+                return Ok(response);
+            }
+        }
+    }
+
     // Hides a product from your library
     pub fn hide_product(&self, game_id: i64) -> EmptyResponse {
         self.rget(
             EMBD,
-            &("/account/hideProduct".to_string() + &game_id.to_string()),
+            &("/account/hideProduct/".to_string() + &game_id.to_string()),
             None,
         )
     }
@@ -316,11 +498,38 @@ impl Gog {
     pub fn reveal_product(&self, game_id: i64) -> EmptyResponse {
         self.rget(
             EMBD,
-            &("/account/revealProduct".to_string() + &game_id.to_string()),
+            &("/account/revealProduct/".to_string() + &game_id.to_string()),
             None,
         )
     }
 
+    // Lists every owned game and movie the user has hidden
+    pub fn hidden_products(&self) -> Result<Vec<ProductDetails>> {
+        let mut products = vec![];
+        for media_type in [1, 2] {
+            products.extend(self.get_all_filtered_products(
+                FilterParams::new().hidden(true).media_type(media_type),
+            )?);
+        }
+        Ok(products)
+    }
+
+    // Hides several products, returning the result for each. Repeated ids are skipped.
+    pub fn hide_products(&self, game_ids: &[i64]) -> Vec<(i64, EmptyResponse)> {
+        dedup(game_ids)
+            .into_iter()
+            .map(|x| (x, self.hide_product(x)))
+            .collect()
+    }
+
+    // Reveals several products, returning the result for each. Repeated ids are skipped.
+    pub fn reveal_products(&self, game_ids: &[i64]) -> Vec<(i64, EmptyResponse)> {
+        dedup(game_ids)
+            .into_iter()
+            .map(|x| (x, self.reveal_product(x)))
+            .collect()
+    }
+
     // Gets the wishlist of the current user
     pub fn wishlist(&self) -> Result<Wishlist> {
         self.fget(EMBD, "/user/wishlist.json", None)
@@ -344,6 +553,94 @@ impl Gog {
         )
     }
 
+    // Gets every wishlisted product, with titles, prices and OS support
+    pub fn wishlist_products(&self) -> Result<Vec<WishlistProduct>> {
+        self.wishlist_products_iter(FilterParams::new().sort(SortOrder::DateAdded))?
+            .collect()
+    }
+
+    // Lazily iterates over wishlisted products matching criteria
+    pub fn wishlist_products_iter(
+        &self,
+        params: FilterParams,
+    ) -> Result<ProductIter<'_, WishlistProducts>> {
+        ProductIter::new(self, params)
+    }
+
+    // Adds several items to the wishlist. Ids that are repeated or already wishlisted are
+    // skipped, so only ids that were actually added are returned, each with its own result.
+    pub fn add_wishlist_bulk(&self, game_ids: &[i64]) -> Result<Vec<(i64, Result<()>)>> {
+        let current = self.wishlist()?.ids();
+        Ok(dedup(game_ids)
+            .into_iter()
+            .filter(|x| !current.contains(x))
+            .map(|x| (x, self.add_wishlist(x).map(|_| ())))
+            .collect())
+    }
+
+    // Removes several items from the wishlist. Ids that are repeated or not wishlisted are
+    // skipped, so only ids that were actually removed are returned, each with its own result.
+    pub fn rm_wishlist_bulk(&self, game_ids: &[i64]) -> Result<Vec<(i64, Result<()>)>> {
+        let current = self.wishlist()?.ids();
+        Ok(dedup(game_ids)
+            .into_iter()
+            .filter(|x| current.contains(x))
+            .map(|x| (x, self.rm_wishlist(x).map(|_| ())))
+            .collect())
+    }
+
+    // Exports the wishlist, so it can be imported into another account
+    pub fn export_wishlist(&self) -> Result<WishlistExport> {
+        Ok(WishlistExport {
+            products: self
+                .wishlist_products()?
+                .into_iter()
+                .map(|x| ExportedProduct {
+                    id: x.id,
+                    title: x.title,
+                })
+                .collect(),
+        })
+    }
+
+    // Adds every product in an export to this account's wishlist
+    pub fn import_wishlist(&self, export: &WishlistExport) -> Result<Vec<(i64, Result<()>)>> {
+        self.add_wishlist_bulk(&export.ids())
+    }
+
+    // Gets who can see the current user's wishlist
+    pub fn wishlist_sharing(&self) -> Result<WishlistSharing> {
+        let info = self.get_pub_info(self.uid(), vec!["wishlistStatus".to_string()])?;
+        Ok(info.wishlist_status.sharing)
+    }
+
+    // Changes who can see the current user's wishlist
+    pub fn save_wishlist_sharing(&self, sharing: WishlistSharing) -> EmptyResponse {
+        self.rget(
+            EMBD,
+            &("/account/save_wishlist_visibility/".to_string() + &(sharing as i32).to_string()),
+            None,
+        )
+    }
+
+    // Gets another user's wishlist, such as a friend's. Returns NotAvailable if they don't share
+    // it with the current user.
+    pub fn user_wishlist(&self, user_id: i64) -> Result<Vec<WishlistProduct>> {
+        let path = format!("/public_wishlist/{}/search", user_id);
+        ProductIter::<WishlistProducts>::with_path(self, &path, FilterParams::new())
+            .and_then(|x| x.collect())
+            .map_err(|e| match e {
+                // GOG refuses wishlists that aren't shared with the current user
+                Error(Network(ref e), _)
+                    if e.status() == Some(StatusCode::FORBIDDEN)
+                        || e.status() == Some(StatusCode::NOT_FOUND) =>
+                {
+                    NotAvailable.into()
+                }
+                e => e,
+            })
+    }
+
     // Sets birthday of account. Date should be in ISO 8601 format
     pub fn save_birthday(&self, bday: &str) -> EmptyResponse {
         self.rget(EMBD, &("/account/save_birthday".to_string() + bday), None)
@@ -429,19 +726,136 @@ impl Gog {
     }
 
     // Returns detailed info about a product/products.
-    pub fn product(&self, ids: Vec<i64>, expand: Vec<String>) -> Result<Vec<Product>> {
+    pub fn product(&self, ids: Vec<i64>, expand: Vec<ProductExpand>) -> Result<Vec<Product>> {
+        let expand: Vec<&str> = expand.iter().map(|x| x.as_str()).collect();
         self.fget(
             API,
             "/products",
             map_p!({
-                "expand": expand.iter().fold("".to_string(), fold_mult),
-                "ids": ids.iter().fold("".to_string(), |acc, x|{
-                    acc + "," + &x.to_string()
-                })
+                "expand": expand,
+                "ids": ids
             }),
         )
     }
 
+    // Gets a product's prices in every currency available in a country. Country should be in
+    // ISO 3166 format.
+    pub fn product_prices(&self, product_id: i64, country: &str) -> Result<Vec<RegionalPrice>> {
+        let res: PricesResponse = self.fget(
+            API,
+            &("/products/".to_string() + &product_id.to_string() + "/prices"),
+            map_p!({ "countryCode": country }),
+        )?;
+        res.embedded
+            .prices
+            .iter()
+            .map(|x| {
+                RegionalPrice::from_raw(product_id, country, x)
+                    .ok_or_else(|| MissingField("price".to_string()).into())
+            })
+            .collect()
+    }
+
+    // Gets a product's price in a country and currency
+    pub fn product_price(
+        &self,
+        product_id: i64,
+        country: &str,
+        currency: Currency,
+    ) -> Result<RegionalPrice> {
+        self.product_prices(product_id, country)?
+            .into_iter()
+            .find(|x| x.is_currency(currency))
+            .ok_or_else(|| NotAvailable.into())
+    }
+
+    // Gets a product's price in the user's country and selected currency
+    pub fn product_price_for_user(
+        &self,
+        product_id: i64,
+        user: &UserData,
+    ) -> Result<RegionalPrice> {
+        self.product_price(product_id, &user.country, user.selected_currency.code)
+    }
+
+    // Gets a page of the user's order history, starting from page 1
+    pub fn orders_page(&self, page: i64) -> Result<OrderPage> {
+        self.fget(
+            EMBD,
+            "/account/settings/orders/data",
+            map_p!({
+                "canceled": 1,
+                "completed": 1,
+                "in_progress": 1,
+                "not_redeemed": 1,
+                "pending": 1,
+                "redeemed": 1,
+                "page": page
+            }),
+        )
+    }
+
+    // Gets the user's whole order history
+    pub fn orders(&self) -> Result<Vec<Order>> {
+        let mut page = 1;
+        let mut orders = vec![];
+        loop {
+            let res = self.orders_page(page)?;
+            orders.extend(res.orders);
+            if page >= res.total_pages {
+                break;
+            } else {
+                page += 1;
+            }
+        }
+        Ok(orders)
+    }
+
+    // Fetches any number of products, splitting the ids into requests of at most
+    // PRODUCT_ID_LIMIT ids and making up to `workers` requests at once. Results are in the same
+    // order as the ids, and a failed request or missing product only affects its own ids. Each id
+    // of a failed request gets a copy of its error of the same kind.
+    pub fn products_batched(
+        &self,
+        ids: Vec<i64>,
+        expand: Vec<ProductExpand>,
+        workers: usize,
+    ) -> Vec<(i64, Result<Product>)> {
+        let unique = dedup(&ids);
+        let chunks: Vec<Vec<i64>> = unique
+            .chunks(PRODUCT_ID_LIMIT)
+            .map(|x| x.to_vec())
+            .collect();
+        let token = match self.fresh_token() {
+            Ok(token) => token,
+            Err(e) => return ids.into_iter().map(|x| (x, Err(copy_error(&e)))).collect(),
+        };
+        let results = pool::run(self, &token, chunks.clone(), workers, |gog, chunk| {
+            gog.product(chunk, expand.clone())
+        });
+        let mut found = BTreeMap::new();
+        for (chunk, res) in chunks.into_iter().zip(results) {
+            match res {
+                Ok(products) => {
+                    found.extend(products.into_iter().map(|x| (x.id, Ok(x))));
+                }
+                Err(e) => {
+                    found.extend(chunk.into_iter().map(|x| (x, Err(copy_error(&e)))));
+                }
+            }
+        }
+        ids.into_iter()
+            .map(|id| {
+                let res = match found.get(&id) {
+                    Some(Ok(product)) => Ok(product.clone()),
+                    Some(Err(e)) => Err(copy_error(e)),
+                    None => Err(NotAvailable.into()),
+                };
+                (id, res)
+            })
+            .collect()
+    }
+
     // Get a list of achievements for a game and user id
     pub fn achievements(&self, product_id: i64, user_id: i64) -> Result<AchievementList> {
         self.fget(
@@ -483,48 +897,145 @@ impl Gog {
 
     // Fetches info about a set of products owned by the user based on search criteria
     pub fn get_filtered_products(&self, params: FilterParams) -> Result<FilteredProducts> {
-        // GOG.com url is just to avoid "cannot be a base" url parse error, as we only need the path anyways
-        let url = reqwest::Url::parse(
-            &("https://gog.com/account/getFilteredProducts".to_string()
-                + &params.to_query_string()),
-        )
-        .unwrap();
-        let path = url.path().to_string() + "?" + url.query().unwrap();
+        self.filter_page(None, &params)
+    }
+
+    // Fetches a single page from one of the filtered product endpoints, or from path if given
+    fn filter_page<P: ProductPage>(&self, path: Option<&str>, params: &FilterParams) -> Result<P> {
+        let path = path
+            .unwrap_or(match P::ENDPOINT {
+                FilterEndpoint::Account => "/account/getFilteredProducts",
+                FilterEndpoint::Store => "/games/ajax/filtered",
+                FilterEndpoint::Wishlist => "/account/wishlist/search",
+            })
+            .to_string()
+            + &params.query(P::ENDPOINT)?;
         self.fget(EMBD, &path, None)
     }
 
     // Fetches info about all products matching criteria
     pub fn get_all_filtered_products(&self, params: FilterParams) -> Result<Vec<ProductDetails>> {
-        let url = reqwest::Url::parse(
-            &("https://gog.com/account/getFilteredProducts".to_string()
-                + &params.to_query_string()),
-        )
-        .unwrap();
-        let mut page = 1;
-        let path = url.path().to_string() + "?" + url.query().unwrap();
+        self.filtered_products_iter(params)?.collect()
+    }
+
+    // Lazily iterates over owned products matching criteria, fetching pages as they're needed.
+    // Starts from the Page param if one is given.
+    pub fn filtered_products_iter(
+        &self,
+        params: FilterParams,
+    ) -> Result<ProductIter<'_, FilteredProducts>> {
+        ProductIter::new(self, params)
+    }
+
+    // Fetches info about a set of products based on search criteria
+    pub fn get_products(&self, params: FilterParams) -> Result<Vec<UnownedProductDetails>> {
+        Ok(self.get_products_page(params)?.products)
+    }
+
+    // Fetches a single page of store products, along with pagination info
+    pub fn get_products_page(&self, params: FilterParams) -> Result<FilteredUnownedProducts> {
+        self.filter_page(None, &params)
+    }
+
+    // Lazily iterates over store products matching criteria, fetching pages as they're needed.
+    // Starts from the Page param if one is given.
+    pub fn products_iter(
+        &self,
+        params: FilterParams,
+    ) -> Result<ProductIter<'_, FilteredUnownedProducts>> {
+        ProductIter::new(self, params)
+    }
+
+    // Searches the store using the newer catalog API
+    pub fn catalog(&self, query: &CatalogQuery) -> Result<CatalogPage> {
+        self.fget(CATALOG, "/v1/catalog", Some(query.params()?))
+    }
+
+    // Fetches every owned game, hidden ones included, along with its details, DLCs and extras.
+    // Details are fetched concurrently, with at most `workers` requests in flight.
+    pub fn library(&self, workers: usize) -> Result<Library> {
+        // Hidden games are only listed when asked for, so both listings are fetched
         let mut products = vec![];
-        loop {
-            let res: FilteredProducts =
-                self.fget(EMBD, &format!("{}&page={}", path, page), None)?;
-            products.push(res.products);
-            if page >= res.total_pages {
-                break;
-            } else {
-                page += 1;
+        for hidden in [false, true] {
+            products.extend(
+                self.get_all_filtered_products(FilterParams::new().media_type(1).hidden(hidden))?,
+            );
+        }
+        let token = self.fresh_token()?;
+        let ids: Vec<i64> = products.iter().map(|x| x.id).collect();
+        let details = pool::run(self, &token, ids, workers, |gog, id| {
+            match gog.get_game_details(id) {
+                Ok(details) => Ok(Some(details)),
+                Err(Error(NotAvailable, _)) => Ok(None),
+                Err(e) => Err(e),
             }
+        });
+        let mut games = vec![];
+        for (product, details) in products.into_iter().zip(details) {
+            games.push(LibraryGame {
+                product,
+                details: details?,
+            });
         }
-        Ok(products.into_iter().flatten().collect())
+        Ok(Library { games })
     }
 
-    // Fetches info about a set of products based on search criteria
-    pub fn get_products(&self, params: FilterParams) -> Result<Vec<UnownedProductDetails>> {
-        // GOG.com url is just to avoid "cannot be a base" url parse error, as we only need the path anyways
-        let url = reqwest::Url::parse(
-            &("https://gog.com/games/ajax/filtered".to_string() + &params.to_query_string()),
-        )
-        .unwrap();
-        let path = url.path().to_string() + "?" + url.query().unwrap();
-        self.nfget(EMBD, &path, None, "products")
+    // Lists every tag the user has created, with how many products carry each
+    pub fn tags(&self) -> Result<Vec<Tag>> {
+        Ok(self.get_filtered_products(FilterParams::new())?.tags)
+    }
+
+    // Lists the ids of every owned game and movie carrying a tag
+    pub fn products_with_tag(&self, tag_id: i64) -> Result<Vec<i64>> {
+        let mut ids = vec![];
+        for media_type in [1, 2] {
+            for product in
+                self.filtered_products_iter(FilterParams::new().tag(tag_id).media_type(media_type))?
+            {
+                ids.push(product?.id);
+            }
+        }
+        Ok(ids)
+    }
+
+    // Renames a tag. GOG has no way to rename a tag in place, so this creates a tag with the new
+    // name, moves every product over to it and deletes the old one. Returns the new tag's id. If
+    // any product can't be moved, the new tag is deleted again and the old one is left alone.
+    pub fn rename_tag(&self, tag_id: i64, name: &str) -> Result<i64> {
+        let products = self.products_with_tag(tag_id)?;
+        let new_id = self.create_tag(name)?;
+        for (product_id, res) in self.add_tag_bulk(&products, new_id) {
+            let failure = match res {
+                Ok(true) => continue,
+                Ok(false) => format!("GOG refused to tag {} with {}", product_id, new_id).into(),
+                Err(e) => e,
+            };
+            if let Err(e) = self.delete_tag(new_id) {
+                warn!("Couldn't delete half-created tag {}: {}", new_id, e);
+            }
+            return Err(failure);
+        }
+        if !self.delete_tag(tag_id)? {
+            return Err(format!("GOG refused to delete tag {}", tag_id).into());
+        }
+        Ok(new_id)
+    }
+
+    // Adds a tag to several products, returning the result for each. Repeated ids are skipped.
+    pub fn add_tag_bulk(&self, product_ids: &[i64], tag_id: i64) -> Vec<(i64, Result<bool>)> {
+        dedup(product_ids)
+            .into_iter()
+            .map(|x| (x, self.add_tag(x, tag_id)))
+            .collect()
+    }
+
+    // Removes a tag from several products, returning the result for each. Repeated ids are
+    // skipped.
+    pub fn rm_tag_bulk(&self, product_ids: &[i64], tag_id: i64) -> Vec<(i64, Result<bool>)> {
+        dedup(product_ids)
+            .into_iter()
+            .map(|x| (x, self.rm_tag(x, tag_id)))
+            .collect()
     }
 
     // Creates a new tag. Returns the tag's id
@@ -685,16 +1196,23 @@ impl Gog {
         start: i64,
         end: i64,
     ) -> Result<Vec<u8>> {
-        Ok(Gog::download_request_range_at(
-            self.token.borrow().access_token.as_str(),
-            url,
-            Collector(Vec::new()),
-            start,
-            end,
-        )?
-        .get_ref()
-        .0
-        .clone())
+        let url = url.into();
+        let fetch = || {
+            Ok(Gog::download_request_range_at(
+                self.token.borrow().access_token.as_str(),
+                url.as_str(),
+                Collector(Vec::new()),
+                start,
+                end,
+            )?
+            .get_ref()
+            .0
+            .clone())
+        };
+        match &self.cassette {
+            Some(cassette) => cassette.send_range(&url, start, end, fetch),
+            None => fetch(),
+        }
     }
 
     // Extracts data on downloads
@@ -702,10 +1220,12 @@ impl Gog {
         let mut zips = vec![];
         let mut responses = self.download_game(downloads.clone());
         for down in downloads {
-            let mut url = BASE.to_string() + &down.manual_url;
+            let mut url = self.domain(BASE).to_string() + &down.manual_url;
             let mut response;
             loop {
-                if let Ok(temp_response) = self.client_noredirect.borrow().get(&url).send() {
+                let client = self.client_noredirect.borrow();
+                let request = client.get(&url).build()?;
+                if let Ok(temp_response) = self.send(&client, request) {
                     response = temp_response;
                     let headers = response.headers();
                     // GOG appears to be inconsistent with returning either 301/302,
@@ -813,8 +1333,69 @@ impl Gog {
     }
 }
 
-fn fold_mult(acc: String, now: &String) -> String {
-    acc + "," + now
+// Removes repeated ids, keeping the first occurrence of each
+fn dedup(ids: &[i64]) -> Vec<i64> {
+    let mut seen = vec![];
+    for id in ids {
+        if !seen.contains(id) {
+            seen.push(*id);
+        }
+    }
+    seen
+}
+
+// Copies an error, for reporting one failure against several items. Errors from other crates
+// can't be copied, so they become a message with the same text.
+fn copy_error(e: &Error) -> Error {
+    match e.kind() {
+        ExpiredToken => ExpiredToken.into(),
+        MissingField(field) => MissingField(field.clone()).into(),
+        NotAvailable => NotAvailable.into(),
+        IncompleteDownload(expected, received) => IncompleteDownload(*expected, *received).into(),
+        ChecksumMismatch(expected, received) => {
+            ChecksumMismatch(expected.clone(), received.clone()).into()
+        }
+        InvalidFilter(reason) => InvalidFilter(reason.clone()).into(),
+        LoginTimeout => LoginTimeout.into(),
+        LoginCancelled(reason) => LoginCancelled(reason.clone()).into(),
+        IncorrectCredentials => IncorrectCredentials.into(),
+        Io(io) => std::io::Error::new(io.kind(), io.to_string()).into(),
+        _ => e.to_string().into(),
+    }
+}
+
+// Builds the url for a request, with params in the query string
+fn request_url(
+    domain: &str,
+    path: &str,
+    params: Option<Map<String, Value>>,
+) -> Result<reqwest::Url> {
+    let mut url = reqwest::Url::parse(&(domain.to_string() + path))
+        .map_err(|e| Error::from(e.to_string()))?;
+    if let Some(params) = params {
+        if !params.is_empty() {
+            let mut pairs = url.query_pairs_mut();
+            for (k, v) in params.iter() {
+                pairs.append_pair(k, &query_value(v));
+            }
+        }
+    }
+    Ok(url)
+}
+
+// Turns a param into a query value. Strings are used as-is rather than JSON-quoted, and arrays
+// become comma separated lists.
+fn query_value(value: &Value) -> String {
+    match value {
+        Value::String(st) => st.clone(),
+        Value::Array(values) => values
+            .iter()
+            .map(query_value)
+            .collect::<Vec<String>>()
+            .join(","),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
 }
 
 fn vec_to_u32(data: &[u8]) -> u32 {
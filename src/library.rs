@@ -0,0 +1,139 @@
+use crate::changelog::*;
+use crate::error::*;
+use crate::gog::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// How many requests Gog::library makes at once by default
+pub const DEFAULT_WORKERS: usize = 8;
+
+// A snapshot of every game the user owns, with its account details
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Library {
+    pub games: Vec<LibraryGame>,
+}
+
+impl Library {
+    // Finds a game in the library by its product id
+    pub fn get(&self, id: i64) -> Option<&LibraryGame> {
+        self.games.iter().find(|x| x.product.id == id)
+    }
+
+    // Returns the product ids of every game in the library
+    pub fn ids(&self) -> Vec<i64> {
+        self.games.iter().map(|x| x.product.id).collect()
+    }
+
+    // Gathers every CD key from games and their DLCs
+    pub fn cd_keys(&self) -> Vec<LibraryCdKey> {
+        let mut keys = vec![];
+        for game in &self.games {
+            let details = match &game.details {
+                Some(details) => details,
+                None => continue,
+            };
+            for details in std::iter::once(details).chain(details.dlcs.iter()) {
+                keys.extend(details.cd_keys().into_iter().map(|x| LibraryCdKey {
+                    product_id: game.id(),
+                    title: details.title.clone(),
+                    label: x.label,
+                    key: x.key,
+                }));
+            }
+        }
+        keys
+    }
+
+    // Gets the changelog entries newer than the installed version of each game. Installed maps
+    // product ids to versions. Games without newer entries are left out.
+    pub fn changes_since(
+        &self,
+        installed: &BTreeMap<i64, String>,
+    ) -> BTreeMap<i64, Vec<ChangelogEntry>> {
+        installed
+            .iter()
+            .filter_map(|(id, version)| {
+                let entries = self.get(*id)?.details.as_ref()?.changelog_entries();
+                let newer: Vec<ChangelogEntry> = changes_since(&entries, version)
+                    .into_iter()
+                    .cloned()
+                    .collect();
+                if newer.is_empty() {
+                    None
+                } else {
+                    Some((*id, newer))
+                }
+            })
+            .collect()
+    }
+
+    // Loads a library snapshot previously saved with to_json
+    pub fn from_json(json: impl AsRef<str>) -> Result<Library> {
+        Ok(serde_json::from_str(json.as_ref())?)
+    }
+
+    // Serializes the library so it can be cached
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+// A CD key found in the library
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LibraryCdKey {
+    // The owned product the key came with
+    pub product_id: i64,
+    // Title of the game or DLC the key is for
+    pub title: String,
+    pub label: Option<String>,
+    pub key: String,
+}
+
+// An owned game, merged from get_filtered_products and get_game_details
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LibraryGame {
+    pub product: ProductDetails,
+    // None if GOG has nothing downloadable for this product
+    pub details: Option<GameDetails>,
+}
+
+impl LibraryGame {
+    pub fn id(&self) -> i64 {
+        self.product.id
+    }
+
+    pub fn title(&self) -> &str {
+        &self.product.title
+    }
+
+    // Whether the user has hidden this product from their library
+    pub fn hidden(&self) -> bool {
+        self.product.is_hidden
+    }
+
+    // Builds for each OS
+    pub fn downloads(&self) -> Option<&Downloads> {
+        self.details.as_ref().map(|x| &x.downloads)
+    }
+
+    pub fn dlcs(&self) -> &[GameDetails] {
+        self.details
+            .as_ref()
+            .map(|x| x.dlcs.as_slice())
+            .unwrap_or(&[])
+    }
+
+    pub fn extras(&self) -> &[Extra] {
+        self.details
+            .as_ref()
+            .map(|x| x.extras.as_slice())
+            .unwrap_or(&[])
+    }
+
+    pub fn tags(&self) -> &[Tag] {
+        self.details
+            .as_ref()
+            .map(|x| x.tags.as_slice())
+            .unwrap_or(&[])
+    }
+}
@@ -0,0 +1,360 @@
+use crate::error::{ErrorKind::*, *};
+use crate::gog::*;
+use crate::library::*;
+use crate::Gog;
+use log::{info, warn};
+use percent_encoding::percent_decode_str;
+use regex::Regex;
+use reqwest::header::CONTENT_LENGTH;
+use reqwest::{Method, Url};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+// Name of the manifest written to the root of a mirror
+pub const MANIFEST_NAME: &str = "manifest.json";
+// Directory, relative to the mirror root, that stale files are moved into
+pub const QUARANTINE_DIR: &str = ".quarantine";
+
+// What to do with previously mirrored files that GOG no longer offers
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StaleAction {
+    Keep,
+    Delete,
+    // Moves the file into QUARANTINE_DIR
+    Quarantine,
+}
+
+// Mirrors installers, patches, DLCs and extras into a directory tree laid out as
+// <slug>/<os>/<lang>/<file> for builds and <slug>/extras/<file> for extras.
+pub struct Mirror {
+    pub root: PathBuf,
+    // Which OSes to mirror builds for
+    pub os: Vec<OS>,
+    pub extras: bool,
+    pub stale: StaleAction,
+}
+
+// A file that has been mirrored
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MirroredFile {
+    pub product_id: i64,
+    // Path relative to the mirror root
+    pub path: String,
+    pub manual_url: String,
+    pub size: u64,
+    pub md5: String,
+}
+
+// Every file in a mirror
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct MirrorManifest {
+    pub files: Vec<MirroredFile>,
+}
+
+// The result of a mirror run. Paths are relative to the mirror root.
+#[derive(Debug, Default)]
+pub struct MirrorReport {
+    pub manifest: MirrorManifest,
+    pub downloaded: Vec<String>,
+    pub skipped: Vec<String>,
+    // Files GOG dropped that were deleted or quarantined
+    pub removed: Vec<String>,
+    // Manual urls that couldn't be mirrored, and why
+    pub failed: Vec<(String, Error)>,
+}
+
+impl Mirror {
+    // Creates a mirror of builds for every OS and extras, quarantining stale files
+    pub fn new(root: impl Into<PathBuf>) -> Mirror {
+        Mirror {
+            root: root.into(),
+            os: vec![OS::Linux, OS::Windows, OS::MacOS],
+            extras: true,
+            stale: StaleAction::Quarantine,
+        }
+    }
+
+    // Reads the manifest of the last run, if there was one
+    pub fn manifest(&self) -> Result<MirrorManifest> {
+        let path = self.root.join(MANIFEST_NAME);
+        if path.exists() {
+            Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+        } else {
+            Ok(MirrorManifest::default())
+        }
+    }
+
+    // Brings the mirror up to date with a library. Files whose size and checksum already match
+    // are skipped. A failure to mirror one file doesn't stop the others.
+    pub fn run(&self, gog: &Gog, library: &Library) -> Result<MirrorReport> {
+        let old = self.manifest()?;
+        let mut report = MirrorReport::default();
+        for game in &library.games {
+            for (dir, manual_url) in self.wanted(game) {
+                match self.mirror_file(gog, &old, game.id(), &dir, &manual_url) {
+                    Ok((file, true)) => {
+                        report.downloaded.push(file.path.clone());
+                        report.manifest.files.push(file);
+                    }
+                    Ok((file, false)) => {
+                        report.skipped.push(file.path.clone());
+                        report.manifest.files.push(file);
+                    }
+                    Err(e) => {
+                        warn!("Couldn't mirror {}: {}", manual_url, e);
+                        report.failed.push((manual_url, e));
+                    }
+                }
+            }
+        }
+        for file in old.files {
+            if report.manifest.files.iter().any(|x| x.path == file.path) {
+                continue;
+            }
+            // Only files GOG dropped from a game still in the library are stale. Files of games
+            // missing from the library, or of OSes not mirrored this time, are left as they are.
+            let stale = report.failed.iter().all(|x| x.0 != file.manual_url)
+                && library
+                    .games
+                    .iter()
+                    .find(|x| x.id() == file.product_id)
+                    .map(|x| {
+                        x.details.is_some()
+                            && (!listed_urls(x).contains(&file.manual_url)
+                                || report
+                                    .manifest
+                                    .files
+                                    .iter()
+                                    .any(|y| y.manual_url == file.manual_url))
+                    })
+                    .unwrap_or(false);
+            if !stale {
+                report.manifest.files.push(file);
+            } else if self.remove_stale(&file.path)? {
+                report.removed.push(file.path);
+            }
+        }
+        fs::create_dir_all(&self.root)?;
+        fs::write(
+            self.root.join(MANIFEST_NAME),
+            serde_json::to_string_pretty(&report.manifest)?,
+        )?;
+        Ok(report)
+    }
+
+    // Lists the directory and manual url of every file to mirror for a game
+    fn wanted(&self, game: &LibraryGame) -> Vec<(String, String)> {
+        let mut wanted = vec![];
+        let details = match &game.details {
+            Some(details) => details,
+            None => return wanted,
+        };
+        let slug = &game.product.slug;
+        for details in std::iter::once(details).chain(details.dlcs.iter()) {
+            for os in &self.os {
+                for down in details.downloads.for_os(*os).into_iter().flatten() {
+                    let lang = down.language().unwrap_or_else(|| "unknown".to_string());
+                    wanted.push((
+                        format!("{}/{}/{}", slug, os_dir(*os), lang),
+                        down.manual_url.clone(),
+                    ));
+                }
+            }
+            if self.extras {
+                for extra in &details.extras {
+                    wanted.push((format!("{}/extras", slug), extra.manual_url.clone()));
+                }
+            }
+        }
+        wanted
+    }
+
+    // Mirrors a single file. Returns whether it was actually downloaded. A HEAD request and GOG's
+    // published checksum decide whether the local copy is current, so nothing is downloaded for
+    // files that are.
+    fn mirror_file(
+        &self,
+        gog: &Gog,
+        old: &MirrorManifest,
+        product_id: i64,
+        dir: &str,
+        manual_url: &str,
+    ) -> Result<(MirroredFile, bool)> {
+        let head = gog
+            .follow_download_with(Method::HEAD, manual_url)?
+            .error_for_status()?;
+        let file_url = head.url().clone();
+        let name = file_url
+            .path_segments()
+            .and_then(|mut x| x.next_back())
+            .map(|x| percent_decode_str(x).decode_utf8_lossy().to_string())
+            .ok_or_else(|| Error::from(MissingField("file name".to_string())))?;
+        let size = head
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| x.parse::<u64>().ok())
+            .ok_or_else(|| Error::from(MissingField("content-length".to_string())))?;
+        let gog_md5 = published_md5(gog, &file_url)?;
+        // The name comes from wherever GOG redirected to, so it mustn't lead out of the mirror
+        for part in dir.split('/').chain(std::iter::once(name.as_str())) {
+            if part.is_empty() || part == "." || part.contains(['/', '\\']) || part.contains("..") {
+                return Err(format!("unsafe path component {:?}", part).into());
+            }
+        }
+        let rel = format!("{}/{}", dir, name);
+        let path = self.root.join(&rel);
+        if path.metadata().map(|x| x.len()).ok() == Some(size) {
+            // Without a checksum from GOG, the one recorded by the last run is the best there is
+            let known = gog_md5.clone().or_else(|| {
+                old.files
+                    .iter()
+                    .find(|x| x.path == rel && x.size == size)
+                    .map(|x| x.md5.clone())
+            });
+            if let Some(known) = known {
+                if file_md5(&path)? == known {
+                    return Ok((
+                        MirroredFile {
+                            product_id,
+                            path: rel,
+                            manual_url: manual_url.to_string(),
+                            size,
+                            md5: known,
+                        },
+                        false,
+                    ));
+                }
+            }
+        }
+        info!("Mirroring {} to {}", manual_url, rel);
+        fs::create_dir_all(path.parent().unwrap())?;
+        let part = path.with_file_name(name + ".part");
+        let md5 = match download_to(gog, manual_url, &part, size, gog_md5.as_deref()) {
+            Ok(md5) => md5,
+            Err(e) => {
+                fs::remove_file(&part).ok();
+                return Err(e);
+            }
+        };
+        fs::rename(part, &path)?;
+        Ok((
+            MirroredFile {
+                product_id,
+                path: rel,
+                manual_url: manual_url.to_string(),
+                size,
+                md5,
+            },
+            true,
+        ))
+    }
+
+    // Deals with a file GOG no longer offers. Returns whether anything was done.
+    fn remove_stale(&self, rel: &str) -> Result<bool> {
+        let path = self.root.join(rel);
+        if !path.exists() {
+            return Ok(false);
+        }
+        match self.stale {
+            StaleAction::Keep => return Ok(false),
+            StaleAction::Delete => fs::remove_file(path)?,
+            StaleAction::Quarantine => {
+                let dest = self.root.join(QUARANTINE_DIR).join(rel);
+                fs::create_dir_all(dest.parent().unwrap())?;
+                fs::rename(path, dest)?;
+            }
+        }
+        Ok(true)
+    }
+}
+
+// The manual url of every file GOG lists for a game, for any OS
+fn listed_urls(game: &LibraryGame) -> Vec<String> {
+    let mut urls = vec![];
+    if let Some(details) = &game.details {
+        for details in std::iter::once(details).chain(details.dlcs.iter()) {
+            for os in [OS::Linux, OS::Windows, OS::MacOS] {
+                for down in details.downloads.for_os(os).into_iter().flatten() {
+                    urls.push(down.manual_url.clone());
+                }
+            }
+            urls.extend(details.extras.iter().map(|x| x.manual_url.clone()));
+        }
+    }
+    urls
+}
+
+// Downloads a file to path, checking its size and, if GOG published one, its checksum. Returns
+// the md5 of what was downloaded.
+fn download_to(
+    gog: &Gog,
+    manual_url: &str,
+    path: &Path,
+    size: u64,
+    expected_md5: Option<&str>,
+) -> Result<String> {
+    let mut response = gog.follow_download(manual_url)?.error_for_status()?;
+    let mut out = fs::File::create(path)?;
+    let mut context = md5::Context::new();
+    let mut buffer = vec![0; 64 * 1024];
+    let mut written = 0;
+    loop {
+        let read = response.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        context.consume(&buffer[..read]);
+        out.write_all(&buffer[..read])?;
+        written += read as u64;
+    }
+    if written != size {
+        return Err(IncompleteDownload(size, written).into());
+    }
+    let md5 = format!("{:x}", context.compute());
+    match expected_md5 {
+        Some(expected) if expected != md5 => {
+            Err(ChecksumMismatch(expected.to_string(), md5).into())
+        }
+        _ => Ok(md5),
+    }
+}
+
+// GOG publishes the checksum of each file in an XML document next to it, at the same url with
+// ".xml" appended, like <file name="setup.exe" md5="..." total_size="...">. Returns None for
+// files without one.
+fn published_md5(gog: &Gog, file_url: &Url) -> Result<Option<String>> {
+    let mut url = file_url.clone();
+    url.set_path(&(file_url.path().to_string() + ".xml"));
+    let client = gog.client.borrow();
+    let response = gog.send(&client, client.get(url).build()?)?;
+    if !response.status().is_success() {
+        return Ok(None);
+    }
+    let md5 = Regex::new(r#"<file\b[^>]*\bmd5="([0-9a-fA-F]{32})""#).unwrap();
+    Ok(md5.captures(&response.text()?).map(|x| x[1].to_lowercase()))
+}
+
+fn os_dir(os: OS) -> &'static str {
+    match os {
+        OS::Linux => "linux",
+        OS::Windows => "windows",
+        OS::MacOS => "mac",
+    }
+}
+
+fn file_md5(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut context = md5::Context::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        context.consume(&buffer[..read]);
+    }
+    Ok(format!("{:x}", context.compute()))
+}
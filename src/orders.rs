@@ -0,0 +1,106 @@
+use crate::error::*;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use time::OffsetDateTime;
+
+// A page of the user's order history
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderPage {
+    pub orders: Vec<Order>,
+    pub total_pages: i64,
+}
+
+// A single purchase
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Order {
+    pub public_id: String,
+    // Unix timestamp of the purchase
+    pub date: i64,
+    pub status: String,
+    pub payment_method: Option<String>,
+    pub total: OrderMoney,
+    #[serde(default)]
+    pub store_credit_used: Option<OrderMoney>,
+    pub receipt_link: Option<String>,
+    pub gift_recipient: Option<String>,
+    pub products: Vec<OrderProduct>,
+}
+
+// A line item in an order
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderProduct {
+    pub id: String,
+    pub title: String,
+    pub price: OrderMoney,
+    #[serde(default)]
+    pub is_refunded: bool,
+}
+
+impl OrderProduct {
+    // Parses the product id, which GOG returns as a string
+    pub fn product_id(&self) -> Option<i64> {
+        self.id.parse().ok()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OrderMoney {
+    pub amount: String,
+    pub symbol: String,
+    // ISO 4217 currency code
+    pub code: String,
+}
+
+impl OrderMoney {
+    pub fn decimal(&self) -> Option<Decimal> {
+        Decimal::from_str(&self.amount).ok()
+    }
+}
+
+// Exports orders as pretty-printed JSON
+pub fn to_json(orders: &[Order]) -> Result<String> {
+    Ok(serde_json::to_string_pretty(orders)?)
+}
+
+// Exports orders as CSV, with one row per line item
+pub fn to_csv(orders: &[Order]) -> String {
+    let mut csv = String::from(
+        "order_id,date,status,payment_method,order_total,currency,product_id,title,price,refunded\n",
+    );
+    for order in orders {
+        let date = OffsetDateTime::from_unix_timestamp(order.date)
+            .map(|x| x.date().to_string())
+            .unwrap_or_default();
+        for product in &order.products {
+            let row = [
+                order.public_id.as_str(),
+                &date,
+                &order.status,
+                order.payment_method.as_deref().unwrap_or(""),
+                &order.total.amount,
+                &order.total.code,
+                &product.id,
+                &product.title,
+                &product.price.amount,
+                if product.is_refunded { "true" } else { "false" },
+            ];
+            let row: Vec<String> = row.iter().map(|x| csv_field(x)).collect();
+            csv += &row.join(",");
+            csv.push('\n');
+        }
+    }
+    csv
+}
+
+// Quotes a field if it contains anything CSV treats specially
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
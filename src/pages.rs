@@ -0,0 +1,154 @@
+use crate::error::*;
+use crate::gog::*;
+use crate::Gog;
+use serde::de::DeserializeOwned;
+
+// A page of results from one of the endpoints that take FilterParams
+pub trait ProductPage: DeserializeOwned {
+    type Item;
+    const ENDPOINT: FilterEndpoint;
+    fn into_products(self) -> Vec<Self::Item>;
+    fn total_pages(&self) -> i64;
+    fn total_products(&self) -> i64;
+}
+
+impl ProductPage for FilteredProducts {
+    type Item = ProductDetails;
+    const ENDPOINT: FilterEndpoint = FilterEndpoint::Account;
+    fn into_products(self) -> Vec<ProductDetails> {
+        self.products
+    }
+    fn total_pages(&self) -> i64 {
+        self.total_pages
+    }
+    fn total_products(&self) -> i64 {
+        self.total_products
+    }
+}
+
+impl ProductPage for FilteredUnownedProducts {
+    type Item = UnownedProductDetails;
+    const ENDPOINT: FilterEndpoint = FilterEndpoint::Store;
+    fn into_products(self) -> Vec<UnownedProductDetails> {
+        self.products
+    }
+    fn total_pages(&self) -> i64 {
+        self.total_pages
+    }
+    fn total_products(&self) -> i64 {
+        self.total_games_found + self.total_movies_found
+    }
+}
+
+// Iterates over products, only fetching the next page once the current one is used up. The
+// first page is fetched when the iterator is created, so the totals are known up front. If a
+// page fails to load, its error is returned and iteration stops.
+pub struct ProductIter<'a, P: ProductPage> {
+    gog: &'a Gog,
+    // Where pages are fetched from, if not the endpoint's usual path
+    path: Option<String>,
+    params: FilterParams,
+    next_page: i64,
+    total_pages: i64,
+    total_products: i64,
+    buffer: std::vec::IntoIter<P::Item>,
+    failed: bool,
+}
+
+impl<'a, P: ProductPage> ProductIter<'a, P> {
+    pub(crate) fn new(gog: &'a Gog, params: FilterParams) -> Result<ProductIter<'a, P>> {
+        ProductIter::start(gog, None, params)
+    }
+
+    // Iterates over pages served from path instead, such as another user's wishlist
+    pub(crate) fn with_path(
+        gog: &'a Gog,
+        path: &str,
+        params: FilterParams,
+    ) -> Result<ProductIter<'a, P>> {
+        ProductIter::start(gog, Some(path.to_string()), params)
+    }
+
+    fn start(
+        gog: &'a Gog,
+        path: Option<String>,
+        params: FilterParams,
+    ) -> Result<ProductIter<'a, P>> {
+        let start = params
+            .params
+            .iter()
+            .find_map(|x| match x {
+                FilterParam::Page(page) => Some(*page),
+                _ => None,
+            })
+            .unwrap_or(1);
+        let params = params.page(start);
+        let first: P = gog.filter_page(path.as_deref(), &params)?;
+        Ok(ProductIter {
+            gog,
+            path,
+            params,
+            next_page: start as i64 + 1,
+            total_pages: first.total_pages(),
+            total_products: first.total_products(),
+            buffer: first.into_products().into_iter(),
+            failed: false,
+        })
+    }
+
+    pub fn total_pages(&self) -> i64 {
+        self.total_pages
+    }
+
+    pub fn total_products(&self) -> i64 {
+        self.total_products
+    }
+
+    // The page that will be fetched next. Iteration can be resumed later by passing this as the
+    // Page param.
+    pub fn next_page(&self) -> i64 {
+        self.next_page
+    }
+}
+
+impl<'a, P: ProductPage> Iterator for ProductIter<'a, P> {
+    type Item = Result<P::Item>;
+
+    fn next(&mut self) -> Option<Result<P::Item>> {
+        loop {
+            if let Some(product) = self.buffer.next() {
+                return Some(Ok(product));
+            }
+            if self.failed || self.next_page > self.total_pages {
+                return None;
+            }
+            let params = std::mem::take(&mut self.params).page(self.next_page as i32);
+            let page: Result<P> = self.gog.filter_page(self.path.as_deref(), &params);
+            self.params = params;
+            match page {
+                Ok(page) => {
+                    self.next_page += 1;
+                    self.buffer = page.into_products().into_iter();
+                }
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+impl ProductPage for WishlistProducts {
+    type Item = WishlistProduct;
+    const ENDPOINT: FilterEndpoint = FilterEndpoint::Wishlist;
+    fn into_products(self) -> Vec<WishlistProduct> {
+        self.products
+    }
+    fn total_pages(&self) -> i64 {
+        self.total_pages
+    }
+    fn total_products(&self) -> i64 {
+        self.total_products
+    }
+}
@@ -0,0 +1,38 @@
+use crate::token::Token;
+use crate::Gog;
+use std::sync::Mutex;
+use std::thread;
+
+// Runs f over every item using at most `workers` threads. Each worker gets its own Gog forked
+// from gog with the given token, since Gog itself can't be shared between threads. Results are
+// returned in the same order as the input.
+pub(crate) fn run<I, T, F>(gog: &Gog, token: &Token, items: Vec<I>, workers: usize, f: F) -> Vec<T>
+where
+    I: Send,
+    T: Send,
+    F: Fn(&Gog, I) -> T + Sync,
+{
+    let len = items.len();
+    let queue = Mutex::new(items.into_iter().enumerate());
+    let results = Mutex::new(Vec::with_capacity(len));
+    let gogs: Vec<Gog> = (0..workers.clamp(1, len.max(1)))
+        .map(|_| gog.fork(token.clone()))
+        .collect();
+    let (queue_ref, results_ref, f) = (&queue, &results, &f);
+    thread::scope(|s| {
+        for gog in gogs {
+            s.spawn(move || loop {
+                let next = queue_ref.lock().unwrap().next();
+                if let Some((i, item)) = next {
+                    let res = f(&gog, item);
+                    results_ref.lock().unwrap().push((i, res));
+                } else {
+                    break;
+                }
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|x| x.0);
+    results.into_iter().map(|x| x.1).collect()
+}
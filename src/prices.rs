@@ -0,0 +1,166 @@
+use crate::error::*;
+use crate::gog::Currency;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+// Raw response from /products/{id}/prices
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct PricesResponse {
+    #[serde(rename = "_embedded")]
+    pub embedded: EmbeddedPrices,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct EmbeddedPrices {
+    pub prices: Vec<RawPrice>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RawPrice {
+    pub currency: RawCurrency,
+    // Amounts are in the currency's minor unit followed by its code, like "999 USD"
+    pub base_price: String,
+    pub final_price: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct RawCurrency {
+    pub code: String,
+}
+
+// A product's price in one country and currency
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RegionalPrice {
+    pub product_id: i64,
+    // ISO 3166 country code
+    pub country: String,
+    // ISO 4217 currency code
+    pub currency: String,
+    pub base: Decimal,
+    #[serde(rename = "final")]
+    pub final_price: Decimal,
+}
+
+impl RegionalPrice {
+    pub(crate) fn from_raw(
+        product_id: i64,
+        country: &str,
+        raw: &RawPrice,
+    ) -> Option<RegionalPrice> {
+        Some(RegionalPrice {
+            product_id,
+            country: country.to_string(),
+            currency: raw.currency.code.clone(),
+            base: parse_minor(&raw.base_price)?,
+            final_price: parse_minor(&raw.final_price)?,
+        })
+    }
+
+    pub fn is_discounted(&self) -> bool {
+        self.final_price < self.base
+    }
+
+    // Discount as a whole percentage of the base price
+    pub fn discount_percentage(&self) -> Decimal {
+        if self.base.is_zero() {
+            Decimal::ZERO
+        } else {
+            ((self.base - self.final_price) / self.base * Decimal::ONE_HUNDRED).round()
+        }
+    }
+
+    pub fn is_currency(&self, currency: Currency) -> bool {
+        self.currency == currency.to_string()
+    }
+}
+
+// Parses amounts like "999 USD" in minor units into a decimal like 9.99
+fn parse_minor(amount: &str) -> Option<Decimal> {
+    let minor: i64 = amount.split_whitespace().next()?.parse().ok()?;
+    Some(Decimal::new(minor, 2))
+}
+
+// A price seen at a certain time
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PriceSnapshot {
+    // Unix timestamp of when the price was recorded
+    pub recorded_at: u64,
+    pub price: RegionalPrice,
+}
+
+// Records prices over time, keyed by product id. Can be saved to and loaded from JSON.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct PriceHistory {
+    pub snapshots: BTreeMap<i64, Vec<PriceSnapshot>>,
+}
+
+impl PriceHistory {
+    pub fn new() -> PriceHistory {
+        PriceHistory::default()
+    }
+
+    // Loads a history saved with save. Returns an empty history if the file doesn't exist.
+    pub fn load(path: impl AsRef<Path>) -> Result<PriceHistory> {
+        let path = path.as_ref();
+        if path.exists() {
+            Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+        } else {
+            Ok(PriceHistory::default())
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    // Records a price as seen now
+    pub fn record(&mut self, price: RegionalPrice) {
+        let recorded_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("System time is before Unix Epoch")
+            .as_secs();
+        self.record_at(price, recorded_at);
+    }
+
+    // Records a price as seen at a unix timestamp
+    pub fn record_at(&mut self, price: RegionalPrice, recorded_at: u64) {
+        let snapshots = self.snapshots.entry(price.product_id).or_default();
+        snapshots.push(PriceSnapshot { recorded_at, price });
+        snapshots.sort_by_key(|x| x.recorded_at);
+    }
+
+    // Every snapshot of a product in a currency, oldest first
+    pub fn history(&self, product_id: i64, currency: Currency) -> Vec<&PriceSnapshot> {
+        self.snapshots
+            .get(&product_id)
+            .into_iter()
+            .flatten()
+            .filter(|x| x.price.is_currency(currency))
+            .collect()
+    }
+
+    // The most recently recorded price of a product in a currency
+    pub fn latest(&self, product_id: i64, currency: Currency) -> Option<&PriceSnapshot> {
+        self.history(product_id, currency).pop()
+    }
+
+    // The lowest final price ever recorded for a product in a currency
+    pub fn lowest(&self, product_id: i64, currency: Currency) -> Option<&PriceSnapshot> {
+        self.history(product_id, currency)
+            .into_iter()
+            .min_by_key(|x| x.price.final_price)
+    }
+
+    // Whether the most recently recorded price is discounted
+    pub fn currently_discounted(&self, product_id: i64, currency: Currency) -> bool {
+        self.latest(product_id, currency)
+            .map(|x| x.price.is_discounted())
+            .unwrap_or(false)
+    }
+}
@@ -0,0 +1,115 @@
+use crate::error::*;
+use crate::gog::*;
+use crate::library::*;
+use crate::Gog;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+// A change noticed while syncing
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub enum SyncEvent {
+    GameAdded(i64),
+    GameRemoved(i64),
+    WishlistChanged { added: Vec<i64>, removed: Vec<i64> },
+    // The full list of the user's ratings after the change
+    RatingsChanged(Vec<(String, i64)>),
+    // The full list of reviews the user has voted on after the change
+    ReviewVotesChanged(Vec<i64>),
+}
+
+// Incrementally keeps a library snapshot up to date, using the checksums from get_user_data to
+// only refetch sections that changed. The whole state can be saved and restored between runs.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct LibrarySync {
+    // Checksums as of the last poll
+    pub checksum: Option<Checksum>,
+    pub library: Option<Library>,
+    pub wishlist: Vec<i64>,
+    pub ratings: Vec<(String, i64)>,
+    pub review_votes: Vec<i64>,
+    // How many requests to make at once when refetching the library
+    #[serde(skip, default = "default_workers")]
+    pub workers: usize,
+}
+
+fn default_workers() -> usize {
+    DEFAULT_WORKERS
+}
+
+impl LibrarySync {
+    pub fn new() -> LibrarySync {
+        LibrarySync {
+            workers: DEFAULT_WORKERS,
+            ..Default::default()
+        }
+    }
+
+    // Loads sync state saved by save
+    pub fn load(path: impl AsRef<Path>) -> Result<LibrarySync> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    // Checks the user's checksums and refetches any sections that changed since the last poll.
+    // The first poll fetches everything and reports all of it as new.
+    pub fn poll(&mut self, gog: &Gog) -> Result<Vec<SyncEvent>> {
+        // Each section's checksum is only stored once that section has been refetched, so a
+        // failure part way through doesn't cause finished sections to be refetched next time
+        let new = gog.get_user_data()?.checksum;
+        let old = self.checksum.clone().unwrap_or_default();
+        let mut events = vec![];
+        if self.library.is_none() || new.games != old.games {
+            let library = gog.library(self.workers)?;
+            let before: BTreeSet<i64> = self
+                .library
+                .as_ref()
+                .map(|x| x.ids().into_iter().collect())
+                .unwrap_or_default();
+            let after: BTreeSet<i64> = library.ids().into_iter().collect();
+            events.extend(after.difference(&before).map(|x| SyncEvent::GameAdded(*x)));
+            events.extend(
+                before
+                    .difference(&after)
+                    .map(|x| SyncEvent::GameRemoved(*x)),
+            );
+            self.library = Some(library);
+            self.checksum.get_or_insert_with(Checksum::default).games = new.games.clone();
+        }
+        if new.wishlist != old.wishlist {
+            let wishlist: BTreeSet<i64> = gog.wishlist()?.ids().into_iter().collect();
+            let before: BTreeSet<i64> = self.wishlist.iter().cloned().collect();
+            let added: Vec<i64> = wishlist.difference(&before).cloned().collect();
+            let removed: Vec<i64> = before.difference(&wishlist).cloned().collect();
+            if !added.is_empty() || !removed.is_empty() {
+                events.push(SyncEvent::WishlistChanged { added, removed });
+            }
+            self.wishlist = wishlist.into_iter().collect();
+            self.checksum.get_or_insert_with(Checksum::default).wishlist = new.wishlist.clone();
+        }
+        if new.games_rating != old.games_rating {
+            let ratings = gog.game_ratings()?;
+            if ratings != self.ratings {
+                events.push(SyncEvent::RatingsChanged(ratings.clone()));
+            }
+            self.ratings = ratings;
+            self.checksum
+                .get_or_insert_with(Checksum::default)
+                .games_rating = new.games_rating.clone();
+        }
+        if new.reviews_votes != old.reviews_votes {
+            let votes = gog.voted_reviews()?;
+            if votes != self.review_votes {
+                events.push(SyncEvent::ReviewVotesChanged(votes.clone()));
+            }
+            self.review_votes = votes;
+        }
+        self.checksum = Some(new);
+        Ok(events)
+    }
+}
@@ -0,0 +1,159 @@
+use crate::error::*;
+use crate::Gog;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+// A single call needed to bring the user's tags to the desired state
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum TagAction {
+    Create(String),
+    Delete(String),
+    Attach { tag: String, product_id: i64 },
+    Detach { tag: String, product_id: i64 },
+}
+
+impl fmt::Display for TagAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TagAction::Create(tag) => write!(f, "+ tag {}", tag),
+            TagAction::Delete(tag) => write!(f, "- tag {}", tag),
+            TagAction::Attach { tag, product_id } => write!(f, "+ {} {}", tag, product_id),
+            TagAction::Detach { tag, product_id } => write!(f, "- {} {}", tag, product_id),
+        }
+    }
+}
+
+// The actions a TagSync would take, in the order they'd be taken. Displays as one action per
+// line, so plans can be reviewed before being applied.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct TagPlan {
+    pub actions: Vec<TagAction>,
+    // Ids of tags that already exist
+    pub tag_ids: BTreeMap<String, i64>,
+}
+
+impl TagPlan {
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+}
+
+impl fmt::Display for TagPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for action in &self.actions {
+            writeln!(f, "{}", action)?;
+        }
+        Ok(())
+    }
+}
+
+// A tag as it currently is on GOG
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct CurrentTag {
+    pub id: i64,
+    pub products: BTreeSet<i64>,
+}
+
+// Syncs the user's tags to a desired state of tag names and the products that carry them
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TagSync {
+    pub desired: BTreeMap<String, BTreeSet<i64>>,
+    // Whether tags that aren't in the desired state should be deleted
+    pub delete_unlisted: bool,
+}
+
+impl TagSync {
+    pub fn new(desired: BTreeMap<String, BTreeSet<i64>>) -> TagSync {
+        TagSync {
+            desired,
+            delete_unlisted: false,
+        }
+    }
+
+    // Reads a desired state from a JSON object of tag names to lists of product ids
+    pub fn from_json(json: impl AsRef<str>) -> Result<TagSync> {
+        Ok(TagSync::new(serde_json::from_str(json.as_ref())?))
+    }
+
+    // Fetches the user's current tags and works out what needs to change, without changing
+    // anything
+    pub fn plan(&self, gog: &Gog) -> Result<TagPlan> {
+        let mut current = BTreeMap::new();
+        for tag in gog.tags()? {
+            let id = tag
+                .tag_id()
+                .ok_or_else(|| Error::from(format!("invalid tag id {}", tag.id)))?;
+            let products = gog.products_with_tag(id)?.into_iter().collect();
+            current.insert(tag.name, CurrentTag { id, products });
+        }
+        Ok(self.plan_against(&current))
+    }
+
+    // Works out the actions needed to get from the current tags to the desired ones
+    pub fn plan_against(&self, current: &BTreeMap<String, CurrentTag>) -> TagPlan {
+        let mut plan = TagPlan {
+            tag_ids: current.iter().map(|x| (x.0.clone(), x.1.id)).collect(),
+            ..Default::default()
+        };
+        let empty = CurrentTag::default();
+        for (tag, wanted) in &self.desired {
+            let existing = match current.get(tag) {
+                Some(existing) => existing,
+                None => {
+                    plan.actions.push(TagAction::Create(tag.clone()));
+                    &empty
+                }
+            };
+            for product_id in wanted.difference(&existing.products) {
+                plan.actions.push(TagAction::Attach {
+                    tag: tag.clone(),
+                    product_id: *product_id,
+                });
+            }
+            for product_id in existing.products.difference(wanted) {
+                plan.actions.push(TagAction::Detach {
+                    tag: tag.clone(),
+                    product_id: *product_id,
+                });
+            }
+        }
+        if self.delete_unlisted {
+            // Deleting a tag removes it from its products too, so nothing needs detaching
+            for tag in current.keys().filter(|x| !self.desired.contains_key(*x)) {
+                plan.actions.push(TagAction::Delete(tag.clone()));
+            }
+        }
+        plan
+    }
+
+    // Carries out a plan, stopping at the first action that fails
+    pub fn apply(&self, gog: &Gog, plan: &TagPlan) -> Result<()> {
+        let mut tag_ids = plan.tag_ids.clone();
+        let id = |tag_ids: &BTreeMap<String, i64>, tag: &str| {
+            tag_ids
+                .get(tag)
+                .cloned()
+                .ok_or_else(|| Error::from(format!("unknown tag {}", tag)))
+        };
+        for action in &plan.actions {
+            let done = match action {
+                TagAction::Create(tag) => {
+                    tag_ids.insert(tag.clone(), gog.create_tag(tag)?);
+                    true
+                }
+                TagAction::Delete(tag) => gog.delete_tag(id(&tag_ids, tag)?)?,
+                TagAction::Attach { tag, product_id } => {
+                    gog.add_tag(*product_id, id(&tag_ids, tag)?)?
+                }
+                TagAction::Detach { tag, product_id } => {
+                    gog.rm_tag(*product_id, id(&tag_ids, tag)?)?
+                }
+            };
+            if !done {
+                return Err(format!("GOG refused to {}", action).into());
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,923 @@
+use crate::containers::*;
+use crate::error::*;
+use crate::gog::status::WishlistSharing;
+use crate::gog::*;
+use crate::token::Token;
+use crate::Gog;
+use serde::Serialize;
+use serde_json::json;
+use serde_json::value::Value;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use tiny_http::{Header, Server};
+
+// How many seconds tokens issued by the fake server are valid for
+pub const TOKEN_LIFETIME: u64 = 3600;
+
+// The state behind a FakeGog. Seed it before making requests and inspect it afterwards.
+#[derive(Debug)]
+pub struct FakeState {
+    pub user_id: String,
+    pub username: String,
+    pub email: String,
+    pub country: String,
+    // The tokens the server accepts. Refreshing replaces both.
+    pub access_token: String,
+    pub refresh_token: String,
+    // Owned games, by id
+    pub games: BTreeMap<i64, FakeGame>,
+    // Products in the store that the user doesn't own, by id
+    pub store: BTreeMap<i64, FakeGame>,
+    pub wishlist: BTreeSet<i64>,
+    pub wishlist_sharing: WishlistSharing,
+    // Tag names, by id
+    pub tags: BTreeMap<i64, String>,
+    // Contents of downloadable files, by manual url
+    pub files: BTreeMap<String, Vec<u8>>,
+    // How many products a page of filtered products holds
+    pub per_page: usize,
+    // Every request received, as "METHOD /path?query"
+    pub requests: Vec<String>,
+    refreshes: u64,
+}
+
+// A game, owned or in the store
+#[derive(Debug, Clone)]
+pub struct FakeGame {
+    pub id: i64,
+    pub title: String,
+    pub slug: String,
+    pub hidden: bool,
+    pub is_movie: bool,
+    pub works_on: WorksOn,
+    // Unix timestamps. Games are purchased in the order they're added unless set otherwise.
+    pub release_date: i64,
+    pub purchased: i64,
+    pub rating: i32,
+    // Ids of the tags on this game
+    pub tags: BTreeSet<i64>,
+    pub downloads: Vec<(OS, Download)>,
+    pub extras: Vec<Extra>,
+    pub cd_key: Option<String>,
+    pub changelog: Option<String>,
+}
+
+// A fake GOG server running in-process. It implements the endpoints this crate uses for user
+// data, public user info, owned games, game details, filtered account and store products, the
+// wishlist and its search, tags, token refreshes and downloads, backed by a FakeState. The server
+// stops when this is dropped.
+pub struct FakeGog {
+    url: String,
+    state: Arc<Mutex<FakeState>>,
+    server: Arc<Server>,
+    thread: Option<JoinHandle<()>>,
+}
+
+// A response from the fake server
+struct Reply {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+impl Default for FakeState {
+    fn default() -> FakeState {
+        FakeState {
+            user_id: "48628349957132247".to_string(),
+            username: "tester".to_string(),
+            email: "tester@example.com".to_string(),
+            country: "US".to_string(),
+            access_token: "access-0".to_string(),
+            refresh_token: "refresh-0".to_string(),
+            games: BTreeMap::new(),
+            store: BTreeMap::new(),
+            wishlist: BTreeSet::new(),
+            wishlist_sharing: WishlistSharing::Public,
+            tags: BTreeMap::new(),
+            files: BTreeMap::new(),
+            per_page: 100,
+            requests: vec![],
+            refreshes: 0,
+        }
+    }
+}
+
+impl FakeGame {
+    fn new(id: i64, title: String) -> FakeGame {
+        let slug = title
+            .to_lowercase()
+            .chars()
+            .map(|x| if x.is_ascii_alphanumeric() { x } else { '_' })
+            .collect();
+        FakeGame {
+            id,
+            title,
+            slug,
+            hidden: false,
+            is_movie: false,
+            works_on: WorksOn {
+                windows: true,
+                linux: true,
+                mac: true,
+            },
+            release_date: 0,
+            purchased: 0,
+            rating: 0,
+            tags: BTreeSet::new(),
+            downloads: vec![],
+            extras: vec![],
+            cd_key: None,
+            changelog: None,
+        }
+    }
+
+    fn runs_on(&self, code: &str) -> bool {
+        match code {
+            "1024" | "2048" => self.works_on.linux,
+            "16" | "32" => self.works_on.mac,
+            _ => self.works_on.windows,
+        }
+    }
+}
+
+impl FakeState {
+    // Adds an owned game with no downloads or extras, available on every OS
+    pub fn add_game(&mut self, id: i64, title: impl Into<String>) -> &mut FakeGame {
+        let purchased = self.games.len() as i64 + 1;
+        self.games.entry(id).or_insert(FakeGame {
+            purchased,
+            ..FakeGame::new(id, title.into())
+        })
+    }
+
+    // Adds a product the user doesn't own to the store, such as one to wishlist
+    pub fn add_store_product(&mut self, id: i64, title: impl Into<String>) -> &mut FakeGame {
+        self.store
+            .entry(id)
+            .or_insert_with(|| FakeGame::new(id, title.into()))
+    }
+
+    // Adds an English installer for a game that serves data. Panics if the game wasn't added.
+    pub fn add_download(&mut self, game_id: i64, os: OS, data: Vec<u8>) -> Download {
+        let game = self.games.get_mut(&game_id).expect("unknown game");
+        let manual_url = format!(
+            "/downloads/{}/en{}installer{}",
+            game.slug,
+            os_number(os),
+            game.downloads.len()
+        );
+        let download = Download {
+            manual_url: manual_url.clone(),
+            downloader_url: None,
+            name: game.title.clone(),
+            version: Some("1.0".to_string()),
+            date: String::new(),
+            size: format_size(data.len()),
+        };
+        game.downloads.push((os, download.clone()));
+        self.files.insert(manual_url, data);
+        download
+    }
+
+    // Adds an extra for a game that serves data. Panics if the game wasn't added.
+    pub fn add_extra(&mut self, game_id: i64, name: &str, kind: &str, data: Vec<u8>) -> Extra {
+        let game = self.games.get_mut(&game_id).expect("unknown game");
+        let id = game.extras.len() as i64 + 1;
+        let extra = Extra {
+            manual_url: format!("/downloads/{}/{}", game.slug, id),
+            downloader_url: None,
+            name: name.to_string(),
+            r#type: kind.to_string(),
+            info: id,
+            size: format_size(data.len()),
+        };
+        game.extras.push(extra.clone());
+        self.files.insert(extra.manual_url.clone(), data);
+        extra
+    }
+
+    // Creates a tag, returning its id
+    pub fn add_tag(&mut self, name: impl Into<String>) -> i64 {
+        let id = self.tags.keys().next_back().map(|x| x + 1).unwrap_or(1);
+        self.tags.insert(id, name.into());
+        id
+    }
+
+    // A token for the user that the server currently accepts
+    pub fn token(&self) -> Token {
+        Token::from_response(self.token_json().to_string()).unwrap()
+    }
+
+    fn token_json(&self) -> Value {
+        json!({
+            "expires_in": TOKEN_LIFETIME,
+            "scope": "",
+            "token_type": "bearer",
+            "access_token": self.access_token,
+            "user_id": self.user_id,
+            "refresh_token": self.refresh_token,
+            "session_id": "fake-session",
+        })
+    }
+
+    fn tag_list(&self) -> Vec<Tag> {
+        self.tags
+            .iter()
+            .map(|(id, name)| Tag {
+                id: id.to_string(),
+                name: name.clone(),
+                product_count: self
+                    .games
+                    .values()
+                    .filter(|x| x.tags.contains(id))
+                    .count()
+                    .to_string(),
+            })
+            .collect()
+    }
+
+    fn user_data(&self) -> UserData {
+        let usd = || CurrencyInfo {
+            code: Currency::USD,
+            symbol: "$".to_string(),
+        };
+        UserData {
+            country: self.country.clone(),
+            currencies: vec![usd()],
+            selected_currency: usd(),
+            preferred_language: LanguageInfo {
+                code: "en".to_string(),
+                name: "English".to_string(),
+            },
+            rating_brand: "ESRB".to_string(),
+            is_logged_in: true,
+            checksum: Checksum {
+                cart: None,
+                games: Some(checksum(self.games.keys())),
+                wishlist: Some(checksum(self.wishlist.iter())),
+                reviews_votes: None,
+                games_rating: None,
+            },
+            updates: Updates {
+                messages: Some(0),
+                pending_friend_requests: Some(0),
+                unread_chat_messages: Some(0),
+                products: Some(0),
+                forum: Some(0),
+                total: Some(0),
+            },
+            user_id: self.user_id.clone(),
+            username: self.username.clone(),
+            email: self.email.clone(),
+            personalized_product_prices: vec![],
+            personalized_series_prices: vec![],
+        }
+    }
+
+    fn wishlist(&self) -> Wishlist {
+        Wishlist {
+            wishlist: self
+                .wishlist
+                .iter()
+                .map(|x| (x.to_string(), true))
+                .collect(),
+            checksum: checksum(self.wishlist.iter()),
+        }
+    }
+
+    // Game details in the shape GOG returns them, with downloads grouped by language
+    fn game_details(&self, game: &FakeGame) -> Value {
+        let for_os = |os| {
+            Some(
+                game.downloads
+                    .iter()
+                    .filter(|x| x.0 == os)
+                    .map(|x| x.1.clone())
+                    .collect(),
+            )
+        };
+        let downloads = Downloads {
+            windows: for_os(OS::Windows),
+            mac: for_os(OS::MacOS),
+            linux: for_os(OS::Linux),
+        };
+        let details = GameDetails {
+            title: game.title.clone(),
+            background_image: String::new(),
+            cd_key: game.cd_key.clone(),
+            text_information: String::new(),
+            downloads,
+            extras: game.extras.clone(),
+            dlcs: vec![],
+            tags: self
+                .tag_list()
+                .into_iter()
+                .filter(|x| game.tags.contains(&x.tag_id().unwrap()))
+                .collect(),
+            is_pre_order: false,
+            release_timestamp: 0,
+            messages: vec![],
+            changelog: game.changelog.clone(),
+            forum_link: String::new(),
+            is_base_product_missing: false,
+            missing_base_product: None,
+        };
+        let mut value = serde_json::to_value(details).unwrap();
+        let downloads = value["downloads"].take();
+        value["downloads"] = json!([["English", downloads]]);
+        value
+    }
+
+    fn filtered_products(&self, query: &BTreeMap<String, String>) -> FilteredProducts {
+        let tag = query.get("tags").and_then(|x| x.parse::<i64>().ok());
+        let hidden = query.get("hiddenFlag").map(|x| x.as_str()) == Some("1");
+        let games = self
+            .games
+            .values()
+            .filter(|x| tag.map(|tag| x.tags.contains(&tag)).unwrap_or(true))
+            .filter(|x| x.hidden == hidden)
+            .collect();
+        let games = select(games, query, "sortBy");
+        let (page, total_pages, per_page) = self.page_of(games.len(), query);
+        FilteredProducts {
+            products: games
+                .iter()
+                .skip((page - 1) * per_page)
+                .take(per_page)
+                .map(|x| ProductDetails {
+                    title: x.title.clone(),
+                    is_galaxy_compatible: false,
+                    id: x.id,
+                    image: String::new(),
+                    url: format!("/game/{}", x.slug),
+                    works_on: x.works_on.clone(),
+                    category: String::new(),
+                    rating: x.rating,
+                    is_coming_soon: false,
+                    is_movie: x.is_movie,
+                    is_game: !x.is_movie,
+                    slug: x.slug.clone(),
+                    updates: Some(0),
+                    is_new: false,
+                    is_hidden: x.hidden,
+                })
+                .collect(),
+            page: page as i64,
+            total_pages: total_pages as i64,
+            total_products: games.len() as i64,
+            products_per_page: per_page as i64,
+            tags: self.tag_list(),
+        }
+    }
+
+    // Store products, owned or not. Nothing in the fake store is discounted.
+    fn store_products(&self, query: &BTreeMap<String, String>) -> FilteredUnownedProducts {
+        let games = self
+            .games
+            .values()
+            .chain(self.store.values())
+            .filter(|_| query.get("price").map(|x| x.as_str()) != Some("discounted"))
+            .collect();
+        let games = select(games, query, "sort");
+        let (page, total_pages, per_page) = self.page_of(games.len(), query);
+        let date = || DurationEnd {
+            date: "1970-01-01 00:00:00.000000".to_string(),
+            timezone_type: 3,
+            timezone: "UTC".to_string(),
+        };
+        FilteredUnownedProducts {
+            products: games
+                .iter()
+                .skip((page - 1) * per_page)
+                .take(per_page)
+                .map(|x| UnownedProductDetails {
+                    price: fake_price(),
+                    is_discounted: false,
+                    is_in_development: false,
+                    id: x.id,
+                    release_date: x.release_date,
+                    availability: Availability {
+                        is_available: true,
+                        is_available_in_account: self.games.contains_key(&x.id),
+                    },
+                    buyable: true,
+                    sales_visibility: SalesVisibility {
+                        is_active: true,
+                        from_object: date(),
+                        to_object: date(),
+                        from: 0,
+                        to: 0,
+                    },
+                    title: x.title.clone(),
+                    image: String::new(),
+                    url: format!("/game/{}", x.slug),
+                    support_url: String::new(),
+                    forum_url: String::new(),
+                    works_on: x.works_on.clone(),
+                    category: String::new(),
+                    original_category: String::new(),
+                    rating: x.rating as i64,
+                    product_type: 1,
+                    is_coming_soon: false,
+                    is_price_visible: true,
+                    is_movie: x.is_movie,
+                    is_game: !x.is_movie,
+                    slug: x.slug.clone(),
+                })
+                .collect(),
+            page: page as i64,
+            total_pages: total_pages as i64,
+            total_games_found: games.iter().filter(|x| !x.is_movie).count() as i64,
+            total_movies_found: games.iter().filter(|x| x.is_movie).count() as i64,
+        }
+    }
+
+    // Wishlisted products that are owned or in the store. There's no record of when products
+    // were wishlisted, so sorting by date added keeps them in id order.
+    fn wishlist_products(&self, query: &BTreeMap<String, String>) -> WishlistProducts {
+        let games = self
+            .wishlist
+            .iter()
+            .filter_map(|x| self.store.get(x).or_else(|| self.games.get(x)))
+            .collect();
+        let games = select(games, query, "sortBy");
+        let (page, total_pages, per_page) = self.page_of(games.len(), query);
+        WishlistProducts {
+            products: games
+                .iter()
+                .skip((page - 1) * per_page)
+                .take(per_page)
+                .map(|x| WishlistProduct {
+                    id: x.id,
+                    title: x.title.clone(),
+                    slug: x.slug.clone(),
+                    url: format!("/game/{}", x.slug),
+                    image: String::new(),
+                    price: Some(fake_price()),
+                    works_on: x.works_on.clone(),
+                    is_discounted: false,
+                    is_coming_soon: false,
+                    date_added: None,
+                })
+                .collect(),
+            page: page as i64,
+            total_pages: total_pages as i64,
+            total_products: games.len() as i64,
+        }
+    }
+
+    fn pub_info(&self) -> PubInfo {
+        PubInfo {
+            id: self.user_id.clone(),
+            username: self.username.clone(),
+            user_since: 0,
+            avatars: None,
+            friend_status: status::FriendStatus {
+                id: self.user_id.clone(),
+                status: 0,
+                date_created: None,
+                date_accepted: None,
+            },
+            wishlist_status: status::WishlistStatus {
+                sharing: self.wishlist_sharing,
+                url: format!("/u/{}/wishlist", self.username),
+            },
+            blocked_status: None,
+            chat_status: None,
+        }
+    }
+
+    // The page asked for, the number of pages and how many products a page holds
+    fn page_of(&self, count: usize, query: &BTreeMap<String, String>) -> (usize, usize, usize) {
+        let per_page = self.per_page.max(1);
+        let page = query
+            .get("page")
+            .and_then(|x| x.parse::<usize>().ok())
+            .unwrap_or(1)
+            .max(1);
+        (page, count.div_ceil(per_page).max(1), per_page)
+    }
+
+    // Changes a game's tags, returning whether both the game and tag exist
+    fn set_tag(&mut self, query: &BTreeMap<String, String>, attach: bool) -> bool {
+        let id = |key: &str| query.get(key).and_then(|x| x.parse::<i64>().ok());
+        let (product_id, tag_id) = match (id("product_id"), id("tag_id")) {
+            (Some(product_id), Some(tag_id)) => (product_id, tag_id),
+            _ => return false,
+        };
+        if !self.tags.contains_key(&tag_id) {
+            return false;
+        }
+        let game = match self.games.get_mut(&product_id) {
+            Some(game) => game,
+            None => return false,
+        };
+        if attach {
+            game.tags.insert(tag_id);
+        } else {
+            game.tags.remove(&tag_id);
+        }
+        true
+    }
+}
+
+impl FakeGog {
+    // Starts a server on a random localhost port with an empty state
+    pub fn start() -> Result<FakeGog> {
+        FakeGog::with_state(FakeState::default())
+    }
+
+    // Starts a server on a random localhost port with a pre-seeded state
+    pub fn with_state(state: FakeState) -> Result<FakeGog> {
+        let server = Server::http("127.0.0.1:0").map_err(|e| Error::from(e.to_string()))?;
+        let addr = server
+            .server_addr()
+            .to_ip()
+            .ok_or_else(|| Error::from("fake server isn't listening on an ip address"))?;
+        let url = format!("http://{}", addr);
+        let server = Arc::new(server);
+        let state = Arc::new(Mutex::new(state));
+        let thread = {
+            let (server, state, url) = (server.clone(), state.clone(), url.clone());
+            std::thread::spawn(move || {
+                for request in server.incoming_requests() {
+                    let reply = {
+                        let mut state = state.lock().unwrap();
+                        state
+                            .requests
+                            .push(format!("{} {}", request.method(), request.url()));
+                        route(&mut state, &url, &request)
+                    };
+                    let mut response = tiny_http::Response::from_data(reply.body)
+                        .with_status_code(reply.status)
+                        .with_chunked_threshold(usize::MAX);
+                    for (k, v) in reply.headers {
+                        response.add_header(Header::from_bytes(k, v).unwrap());
+                    }
+                    request.respond(response).ok();
+                }
+            })
+        };
+        Ok(FakeGog {
+            url,
+            state,
+            server,
+            thread: Some(thread),
+        })
+    }
+
+    // The base url of the server, like http://127.0.0.1:1234
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    // Locks the state for seeding or inspection. Requests wait until the guard is dropped.
+    pub fn state(&self) -> MutexGuard<'_, FakeState> {
+        self.state.lock().unwrap()
+    }
+
+    // A token that the server currently accepts
+    pub fn token(&self) -> Token {
+        self.state().token()
+    }
+
+    // A Gog that sends every request to this server
+    pub fn gog(&self) -> Gog {
+        Gog::new(self.token()).with_base_url(self.url.clone())
+    }
+}
+
+impl Drop for FakeGog {
+    fn drop(&mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+impl Reply {
+    fn json(status: u16, body: impl Serialize) -> Reply {
+        Reply {
+            status,
+            headers: vec![("Content-Type", "application/json".to_string())],
+            body: serde_json::to_vec(&body).unwrap(),
+        }
+    }
+
+    fn status(status: u16) -> Reply {
+        Reply {
+            status,
+            headers: vec![],
+            body: vec![],
+        }
+    }
+}
+
+fn route(state: &mut FakeState, base: &str, request: &tiny_http::Request) -> Reply {
+    let url = match reqwest::Url::parse(&(base.to_string() + request.url())) {
+        Ok(url) => url,
+        Err(_) => return Reply::status(400),
+    };
+    let path = url.path();
+    let query: BTreeMap<String, String> = url.query_pairs().into_owned().collect();
+    let header = |name: &'static str| {
+        request
+            .headers()
+            .iter()
+            .find(|x| x.field.equiv(name))
+            .map(|x| x.value.as_str().to_string())
+    };
+    if path == "/token" {
+        return refresh(state, &query);
+    }
+    // Download links are signed, so they're served without checking the token
+    if let Some(manual_url) = path.strip_prefix("/files") {
+        return serve_file(state, manual_url, header("Range"));
+    }
+    if header("Authorization") != Some(format!("Bearer {}", state.access_token)) {
+        return Reply::json(401, json!({ "error": "invalid_token" }));
+    }
+    let id_after = |prefix: &str| {
+        path.strip_prefix(prefix)
+            .map(|x| x.trim_end_matches(".json"))
+            .and_then(|x| x.parse::<i64>().ok())
+    };
+    if path.starts_with("/downloads/") {
+        return if state.files.contains_key(path) {
+            Reply {
+                status: 302,
+                headers: vec![("Location", format!("{}/files{}", base, path))],
+                body: vec![],
+            }
+        } else {
+            Reply::status(404)
+        };
+    }
+    if let Some(id) = id_after("/account/gameDetails/") {
+        return match state.games.get(&id) {
+            Some(game) => Reply::json(200, state.game_details(game)),
+            None => Reply::json(200, json!([])),
+        };
+    }
+    if let Some(id) = id_after("/users/info/") {
+        return if id.to_string() == state.user_id {
+            Reply::json(200, state.pub_info())
+        } else {
+            Reply::status(404)
+        };
+    }
+    if let Some(sharing) = id_after("/account/save_wishlist_visibility/") {
+        return match WishlistSharing::try_from(sharing as i32) {
+            Ok(sharing) => {
+                state.wishlist_sharing = sharing;
+                Reply::status(200)
+            }
+            Err(_) => Reply::status(400),
+        };
+    }
+    if let Some(rest) = path.strip_prefix("/public_wishlist/") {
+        let shared = state.wishlist_sharing != WishlistSharing::Private;
+        return if rest == format!("{}/search", state.user_id) && shared {
+            Reply::json(200, state.wishlist_products(&query))
+        } else {
+            Reply::status(403)
+        };
+    }
+    if let Some(id) = id_after("/user/wishlist/add/") {
+        state.wishlist.insert(id);
+        return Reply::json(200, state.wishlist());
+    }
+    if let Some(id) = id_after("/user/wishlist/remove/") {
+        state.wishlist.remove(&id);
+        return Reply::json(200, state.wishlist());
+    }
+    for (prefix, hidden) in [
+        ("/account/hideProduct/", true),
+        ("/account/revealProduct/", false),
+    ] {
+        if let Some(id) = id_after(prefix) {
+            return match state.games.get_mut(&id) {
+                Some(game) => {
+                    game.hidden = hidden;
+                    Reply::status(200)
+                }
+                None => Reply::status(404),
+            };
+        }
+    }
+    match path {
+        "/userData.json" => Reply::json(200, state.user_data()),
+        "/user/data/games" => Reply::json(
+            200,
+            OwnedGames {
+                owned: state.games.keys().cloned().collect(),
+            },
+        ),
+        "/account/getFilteredProducts" => Reply::json(200, state.filtered_products(&query)),
+        "/account/wishlist/search" => Reply::json(200, state.wishlist_products(&query)),
+        "/games/ajax/filtered" => Reply::json(200, state.store_products(&query)),
+        "/user/wishlist.json" => Reply::json(200, state.wishlist()),
+        "/account/tags/add" => match query.get("name") {
+            Some(name) => {
+                let id = state.add_tag(name.clone());
+                Reply::json(200, json!({ "id": id.to_string() }))
+            }
+            None => Reply::status(400),
+        },
+        "/account/tags/delete" => {
+            let id = query.get("tag_id").and_then(|x| x.parse::<i64>().ok());
+            match id.and_then(|id| state.tags.remove(&id).map(|_| id)) {
+                Some(id) => {
+                    for game in state.games.values_mut() {
+                        game.tags.remove(&id);
+                    }
+                    Reply::json(
+                        200,
+                        StatusDel {
+                            status: "deleted".to_string(),
+                        },
+                    )
+                }
+                None => Reply::json(
+                    200,
+                    StatusDel {
+                        status: "not found".to_string(),
+                    },
+                ),
+            }
+        }
+        "/account/tags/attach" => Reply::json(
+            200,
+            Success {
+                success: state.set_tag(&query, true),
+            },
+        ),
+        "/account/tags/detach" => Reply::json(
+            200,
+            Success {
+                success: state.set_tag(&query, false),
+            },
+        ),
+        _ => Reply::status(404),
+    }
+}
+
+fn refresh(state: &mut FakeState, query: &BTreeMap<String, String>) -> Reply {
+    let valid = query.get("grant_type").map(|x| x.as_str()) == Some("refresh_token")
+        && query.get("refresh_token") == Some(&state.refresh_token);
+    if !valid {
+        return Reply::json(400, json!({ "error": "invalid_grant" }));
+    }
+    state.refreshes += 1;
+    state.access_token = format!("access-{}", state.refreshes);
+    state.refresh_token = format!("refresh-{}", state.refreshes);
+    Reply::json(200, state.token_json())
+}
+
+// Serves a file, or the part of it asked for with a Range header
+fn serve_file(state: &FakeState, manual_url: &str, range: Option<String>) -> Reply {
+    // The checksum GOG publishes next to each file
+    if let Some(data) = manual_url
+        .strip_suffix(".xml")
+        .and_then(|x| state.files.get(x))
+    {
+        let xml = format!(
+            r#"<file name="{}" md5="{:x}" total_size="{}"></file>"#,
+            manual_url
+                .rsplit('/')
+                .next()
+                .unwrap()
+                .trim_end_matches(".xml"),
+            md5::compute(data),
+            data.len()
+        );
+        return Reply {
+            status: 200,
+            headers: vec![("Content-Type", "application/xml".to_string())],
+            body: xml.into_bytes(),
+        };
+    }
+    let data = match state.files.get(manual_url) {
+        Some(data) => data,
+        None => return Reply::status(404),
+    };
+    let len = data.len();
+    let mut headers = vec![("Accept-Ranges", "bytes".to_string())];
+    let range = match range {
+        Some(range) => range,
+        None => {
+            return Reply {
+                status: 200,
+                headers,
+                body: data.clone(),
+            }
+        }
+    };
+    let bounds = range.strip_prefix("bytes=").and_then(|x| x.split_once('-'));
+    let (start, end) = match bounds {
+        Some((start, end)) => (start.parse::<usize>().ok(), end.parse::<usize>().ok()),
+        None => (None, None),
+    };
+    match (start, end) {
+        (Some(start), end) if start < len => {
+            let end = end.unwrap_or(len - 1).min(len - 1).max(start);
+            headers.push(("Content-Range", format!("bytes {}-{}/{}", start, end, len)));
+            Reply {
+                status: 206,
+                headers,
+                body: data[start..=end].to_vec(),
+            }
+        }
+        _ => {
+            headers.push(("Content-Range", format!("bytes */{}", len)));
+            Reply {
+                status: 416,
+                headers,
+                body: vec![],
+            }
+        }
+    }
+}
+
+// Applies the search, media type, OS and sort params every filtered endpoint takes. sort_key is
+// the param the endpoint sorts by.
+fn select<'a>(
+    games: Vec<&'a FakeGame>,
+    query: &BTreeMap<String, String>,
+    sort_key: &str,
+) -> Vec<&'a FakeGame> {
+    let search = query
+        .get("search")
+        .cloned()
+        .unwrap_or_default()
+        .to_lowercase();
+    let movies = query.get("mediaType").map(|x| x.as_str()) == Some("2");
+    let systems: Vec<&str> = query
+        .get("system")
+        .map(|x| x.split(',').collect())
+        .unwrap_or_default();
+    let mut games: Vec<&FakeGame> = games
+        .into_iter()
+        .filter(|x| x.title.to_lowercase().contains(&search))
+        .filter(|x| x.is_movie == movies)
+        .filter(|x| systems.is_empty() || systems.iter().any(|code| x.runs_on(code)))
+        .collect();
+    // Dates and ratings are newest and best first
+    match query.get(sort_key).map(|x| x.as_str()) {
+        Some("title") => games.sort_by(|a, b| a.title.cmp(&b.title)),
+        Some("date_purchased") => games.sort_by_key(|x| Reverse(x.purchased)),
+        Some("release_date") | Some("date") => games.sort_by_key(|x| Reverse(x.release_date)),
+        Some("rating") => games.sort_by_key(|x| Reverse(x.rating)),
+        _ => (),
+    }
+    games
+}
+
+fn fake_price() -> Price {
+    Price {
+        amount: "9.99".to_string(),
+        base_amount: "9.99".to_string(),
+        final_amount: "9.99".to_string(),
+        is_discounted: false,
+        discount_percentage: 0,
+        discount_difference: "0.00".to_string(),
+        symbol: "$".to_string(),
+        is_free: false,
+        discount: 0,
+        is_bonus_store_credit_included: false,
+        bonus_store_credit_amount: "0.00".to_string(),
+    }
+}
+
+fn checksum<'a>(ids: impl Iterator<Item = &'a i64>) -> String {
+    let ids: Vec<String> = ids.map(|x| x.to_string()).collect();
+    format!("{:x}", md5::compute(ids.join(",")))
+}
+
+// The digit GOG puts after the language in installer urls
+fn os_number(os: OS) -> u8 {
+    match os {
+        OS::Windows => 1,
+        OS::MacOS => 2,
+        OS::Linux => 3,
+    }
+}
+
+// Formats a size the way GOG does, like "1.5 MB"
+fn format_size(bytes: usize) -> String {
+    let units = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, units[unit])
+    }
+}
@@ -1,11 +1,21 @@
-use crate::error::{ErrorKind::*, *};
+use crate::error::{Error, ErrorKind::*, *};
+use crate::gog::domains::AUTH;
 use log::{error, info, warn};
 use regex::*;
 use reqwest;
 use select::{document::*, predicate::*};
 use serde::{Deserialize, Serialize};
 use serde_json;
-use std::time::SystemTime;
+use std::io::{BufRead, BufReader, ErrorKind as IoErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+const CLIENT_ID: &str = "46899977096215655";
+// Path on the local listener that GOG redirects to after a browser login
+const REDIRECT_PATH: &str = "/on_login_success";
 
 // An OAuth token. Will usually expire after an hour.
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -39,11 +49,40 @@ impl Token {
 
     // Fetches a token using a login code
     pub fn from_login_code(code: impl Into<String>) -> Result<Token> {
-        let res = reqwest::blocking::get("https://auth.gog.com/token?client_id=46899977096215655&client_secret=9d85c43b1482497dbbce61f6e4aa173a433796eeae2ca8c5f6129f2dc4de46d9&grant_type=authorization_code&redirect_uri=https%3A%2F%2Fembed.gog.com%2Fon_login_success%3Forigin%3Dclient&layout=client2&code=".to_string()+&code.into()+"")?;
+        Token::from_login_code_with(code, "https://embed.gog.com/on_login_success?origin=client")
+    }
+
+    // Fetches a token using a login code that GOG sent to a different redirect uri
+    pub fn from_login_code_with(code: impl Into<String>, redirect_uri: &str) -> Result<Token> {
+        let url = reqwest::Url::parse_with_params(
+            &(AUTH.to_string() + "/token"),
+            &[
+                ("client_id", CLIENT_ID),
+                (
+                    "client_secret",
+                    "9d85c43b1482497dbbce61f6e4aa173a433796eeae2ca8c5f6129f2dc4de46d9",
+                ),
+                ("grant_type", "authorization_code"),
+                ("redirect_uri", redirect_uri),
+                ("layout", "client2"),
+                ("code", &code.into()),
+            ],
+        )
+        .map_err(|e| Error::from(e.to_string()))?;
+        let res = reqwest::blocking::get(url)?;
         let text = res.text()?;
         Token::from_response(text)
     }
 
+    // Logs in through the user's browser with the default BrowserLogin settings. open is given
+    // the url the user should visit.
+    pub fn login_browser<F>(open: F) -> Result<Token>
+    where
+        F: FnOnce(&str),
+    {
+        BrowserLogin::new().run(open)
+    }
+
     pub fn from_home_code(code: impl Into<String>) -> Result<Token> {
         let url = format!("https://auth.gog.com/token?client_id=46899977096215655&client_secret=9d85c43b1482497dbbce61f6e4aa173a433796eeae2ca8c5f6129f2dc4de46d9&grant_type=authorization_code&redirect_uri=https%3A%2F%2Fwww.gog.com%2Fon_login_success&layout=client2&code={}", code.into());
         let res = reqwest::blocking::get(url)?;
@@ -58,7 +97,12 @@ impl Token {
 
     // Attempts to fetch an updated token
     pub fn refresh(&self) -> Result<Token> {
-        let res = reqwest::blocking::get("https://auth.gog.com/token?client_id=46899977096215655&client_secret=9d85c43b1482497dbbce61f6e4aa173a433796eeae2ca8c5f6129f2dc4de46d9&grant_type=refresh_token&redirect_uri=https://embed.gog.com/on_login_success?origin=client&refresh_token=".to_string()+&self.refresh_token)?;
+        self.refresh_from(AUTH)
+    }
+
+    // Attempts to fetch an updated token from a different auth server
+    pub fn refresh_from(&self, auth: &str) -> Result<Token> {
+        let res = reqwest::blocking::get(auth.to_string()+"/token?client_id=46899977096215655&client_secret=9d85c43b1482497dbbce61f6e4aa173a433796eeae2ca8c5f6129f2dc4de46d9&grant_type=refresh_token&redirect_uri=https://embed.gog.com/on_login_success?origin=client&refresh_token="+&self.refresh_token)?;
         Ok(serde_json::from_str(&res.text()?)?)
     }
 
@@ -244,3 +288,156 @@ impl Token {
         }
     }
 }
+
+// Logs in through the user's browser. A listener on localhost receives GOG's redirect after the
+// user logs in, and the login code it carries is exchanged for a token.
+#[derive(Debug, Clone)]
+pub struct BrowserLogin {
+    // Port to listen for the redirect on. 0 picks a free port.
+    pub port: u16,
+    // How long to wait for the user to finish logging in
+    pub timeout: Duration,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Default for BrowserLogin {
+    fn default() -> BrowserLogin {
+        BrowserLogin::new()
+    }
+}
+
+impl BrowserLogin {
+    // Listens on a free port and waits up to five minutes
+    pub fn new() -> BrowserLogin {
+        BrowserLogin {
+            port: 0,
+            timeout: Duration::from_secs(5 * 60),
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn port(mut self, port: u16) -> BrowserLogin {
+        self.port = port;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> BrowserLogin {
+        self.timeout = timeout;
+        self
+    }
+
+    // A flag that, once set from another thread, stops a running login with LoginCancelled
+    pub fn cancel_handle(&self) -> Arc<AtomicBool> {
+        self.cancelled.clone()
+    }
+
+    // Builds the url the user should visit to log in and be redirected to redirect_uri
+    pub fn auth_url(redirect_uri: &str) -> Result<String> {
+        let url = reqwest::Url::parse_with_params(
+            &(AUTH.to_string() + "/auth"),
+            &[
+                ("client_id", CLIENT_ID),
+                ("redirect_uri", redirect_uri),
+                ("response_type", "code"),
+                ("layout", "client2"),
+            ],
+        )
+        .map_err(|e| Error::from(e.to_string()))?;
+        Ok(url.to_string())
+    }
+
+    // Starts the listener, calls open with the url the user should visit, and exchanges the
+    // login code GOG redirects back with for a token
+    pub fn run<F>(&self, open: F) -> Result<Token>
+    where
+        F: FnOnce(&str),
+    {
+        let (code, redirect_uri) = self.wait_for_code(open)?;
+        Token::from_login_code_with(code, &redirect_uri)
+    }
+
+    // Like run, but returns the login code and the redirect uri it was sent to instead of
+    // exchanging it
+    pub fn wait_for_code<F>(&self, open: F) -> Result<(String, String)>
+    where
+        F: FnOnce(&str),
+    {
+        let listener = TcpListener::bind(("127.0.0.1", self.port))?;
+        listener.set_nonblocking(true)?;
+        let redirect_uri = format!(
+            "http://127.0.0.1:{}{}",
+            listener.local_addr()?.port(),
+            REDIRECT_PATH
+        );
+        open(&BrowserLogin::auth_url(&redirect_uri)?);
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if self.cancelled.load(Ordering::SeqCst) {
+                return Err(LoginCancelled("cancelled by caller".to_string()).into());
+            }
+            if Instant::now() >= deadline {
+                return Err(LoginTimeout.into());
+            }
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    if let Some(code) = handle_redirect(stream)? {
+                        return Ok((code, redirect_uri));
+                    }
+                }
+                Err(e) if e.kind() == IoErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(50))
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+// Reads a request to the redirect listener. Returns the login code if GOG sent one, and an error
+// if GOG reported that the login failed. Unrelated requests, like for a favicon, are ignored.
+fn handle_redirect(mut stream: TcpStream) -> Result<Option<String>> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut line = String::new();
+    if BufReader::new(&stream).read_line(&mut line).is_err() {
+        return Ok(None);
+    }
+    let target = line.split_whitespace().nth(1).unwrap_or("/");
+    let url = match reqwest::Url::parse(&("http://127.0.0.1".to_string() + target)) {
+        Ok(url) if url.path() == REDIRECT_PATH => url,
+        _ => {
+            respond(&mut stream, "404 Not Found", "Not found")?;
+            return Ok(None);
+        }
+    };
+    let param = |name: &str| {
+        url.query_pairs()
+            .find(|(k, _)| k == name)
+            .map(|x| x.1.to_string())
+    };
+    if let Some(code) = param("code") {
+        respond(
+            &mut stream,
+            "200 OK",
+            "Logged in to GOG. You can close this window.",
+        )?;
+        Ok(Some(code))
+    } else if let Some(error) = param("error") {
+        respond(&mut stream, "200 OK", "The GOG login was cancelled.")?;
+        Err(LoginCancelled(error).into())
+    } else {
+        respond(&mut stream, "400 Bad Request", "Missing login code")?;
+        Ok(None)
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: &str, message: &str) -> Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        message.len(),
+        message
+    )?;
+    Ok(())
+}
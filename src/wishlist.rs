@@ -0,0 +1,172 @@
+use crate::error::*;
+use crate::gog::*;
+use crate::pool;
+use crate::prices::*;
+use crate::Gog;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+// A portable copy of a wishlist
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WishlistExport {
+    pub products: Vec<ExportedProduct>,
+}
+
+impl WishlistExport {
+    pub fn ids(&self) -> Vec<i64> {
+        self.products.iter().map(|x| x.id).collect()
+    }
+
+    pub fn from_json(json: impl AsRef<str>) -> Result<WishlistExport> {
+        Ok(serde_json::from_str(json.as_ref())?)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+// A product in a wishlist export. The title is only there to make exports readable.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExportedProduct {
+    pub id: i64,
+    pub title: String,
+}
+
+// A price change on a wishlisted game
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum WishlistEvent {
+    // The game went on sale, or its sale got deeper
+    Discounted {
+        price: RegionalPrice,
+        previous: Option<RegionalPrice>,
+    },
+    // The final price dropped to or below the threshold set for the game
+    BelowThreshold {
+        price: RegionalPrice,
+        threshold: Decimal,
+    },
+}
+
+// Watches the user's wishlist for price drops. The last observed price of each game is kept, so
+// events are only emitted when something changes. The state can be saved and restored between
+// runs.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WishlistWatcher {
+    // ISO 3166 country code prices are checked in
+    pub country: String,
+    pub currency: Currency,
+    // Per-product price thresholds
+    pub thresholds: BTreeMap<i64, Decimal>,
+    // Threshold for products without their own
+    pub default_threshold: Option<Decimal>,
+    // Wishlist checksum from the user's data as of the last poll
+    pub checksum: Option<String>,
+    pub ids: Vec<i64>,
+    pub last_prices: BTreeMap<i64, RegionalPrice>,
+    // How many prices to fetch at once
+    #[serde(skip, default = "default_workers")]
+    pub workers: usize,
+}
+
+fn default_workers() -> usize {
+    4
+}
+
+impl WishlistWatcher {
+    pub fn new(country: impl Into<String>, currency: Currency) -> WishlistWatcher {
+        WishlistWatcher {
+            country: country.into(),
+            currency,
+            thresholds: BTreeMap::new(),
+            default_threshold: None,
+            checksum: None,
+            ids: vec![],
+            last_prices: BTreeMap::new(),
+            workers: default_workers(),
+        }
+    }
+
+    // Creates a watcher using the user's country and selected currency
+    pub fn for_user(user: &UserData) -> WishlistWatcher {
+        WishlistWatcher::new(user.country.clone(), user.selected_currency.code)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<WishlistWatcher> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn set_threshold(&mut self, product_id: i64, threshold: Decimal) {
+        self.thresholds.insert(product_id, threshold);
+    }
+
+    // Fetches current prices for every wishlisted game and returns any price drops since the
+    // last poll. The wishlist itself is only refetched when its checksum in the user's data
+    // changes. Games that can't be bought in the watcher's country are skipped. Nothing is stored
+    // unless every price was fetched, so a failed poll can simply be retried.
+    pub fn poll(&mut self, gog: &Gog) -> Result<Vec<WishlistEvent>> {
+        let checksum = gog.get_user_data()?.checksum.wishlist;
+        let ids = if checksum.is_none() || checksum != self.checksum {
+            gog.wishlist()?.ids()
+        } else {
+            self.ids.clone()
+        };
+        let token = gog.fresh_token()?;
+        let prices = pool::run(gog, &token, ids.clone(), self.workers, |gog, id| {
+            gog.product_price(id, &self.country, self.currency)
+        });
+        let mut last_prices = self.last_prices.clone();
+        last_prices.retain(|id, _| ids.contains(id));
+        let mut events = vec![];
+        for price in prices {
+            let price = match price {
+                Ok(price) => price,
+                Err(Error(ErrorKind::NotAvailable, _)) => continue,
+                Err(e) => return Err(e),
+            };
+            let previous = last_prices.insert(price.product_id, price.clone());
+            events.extend(self.events(price, previous));
+        }
+        self.checksum = checksum;
+        self.ids = ids;
+        self.last_prices = last_prices;
+        Ok(events)
+    }
+
+    fn events(&self, price: RegionalPrice, previous: Option<RegionalPrice>) -> Vec<WishlistEvent> {
+        let mut events = vec![];
+        let threshold = self
+            .thresholds
+            .get(&price.product_id)
+            .cloned()
+            .or(self.default_threshold);
+        if let Some(threshold) = threshold {
+            let was_above = previous
+                .as_ref()
+                .map(|x| x.final_price > threshold)
+                .unwrap_or(true);
+            if price.final_price <= threshold && was_above {
+                events.push(WishlistEvent::BelowThreshold {
+                    price: price.clone(),
+                    threshold,
+                });
+            }
+        }
+        let deeper = previous
+            .as_ref()
+            .map(|x| price.final_price < x.final_price)
+            .unwrap_or(true);
+        if price.is_discounted() && deeper {
+            events.push(WishlistEvent::Discounted { price, previous });
+        }
+        events
+    }
+}
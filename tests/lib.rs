@@ -1,6 +1,9 @@
 use std::env::var_os;
+use std::ffi::OsString;
 use std::fs::File;
+use std::path::PathBuf;
 extern crate gog;
+use gog::cassette::*;
 use gog::gog::FilterParam::*;
 use gog::gog::OS::*;
 use gog::gog::*;
@@ -12,8 +15,24 @@ use std::io::Read;
 // "Beneath a Steel Sky" is free from GOG, so a good test case.
 const OWNED_GAME_ID: i64 = 1207658695;
 
+// Setting GOG_RECORD to a directory records each test's requests into <dir>/<test name>.json.
+// Setting GOG_REPLAY to such a directory runs the tests against those recordings, without a
+// token or network access. Without GOG_TOKEN_PATH, recordings committed under tests/cassettes
+// are replayed by default.
 fn get_gog() -> Gog {
-    let path = var_os("GOG_TOKEN_PATH").unwrap().into_string().unwrap();
+    let committed = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/cassettes");
+    let replay = var_os("GOG_REPLAY").or_else(|| {
+        let found = cassette_path(committed.clone().into_os_string()).exists();
+        (var_os("GOG_TOKEN_PATH").is_none() && found).then(|| committed.into_os_string())
+    });
+    if let Some(dir) = replay {
+        let cassette = Cassette::replay(cassette_path(dir)).unwrap();
+        return Gog::new(cassette.token()).with_cassette(cassette);
+    }
+    let path = var_os("GOG_TOKEN_PATH")
+        .expect("set GOG_TOKEN_PATH, or record this test into tests/cassettes")
+        .into_string()
+        .unwrap();
     let mut token_json = String::new();
     File::open(path)
         .unwrap()
@@ -21,7 +40,19 @@ fn get_gog() -> Gog {
         .unwrap();
     let mut token = Token::from_response(token_json.as_str()).unwrap();
     token = token.refresh().unwrap();
-    Gog::new(token)
+    match var_os("GOG_RECORD") {
+        Some(dir) => {
+            let cassette = Cassette::record(cassette_path(dir), token.user_id.clone());
+            Gog::new(token).with_cassette(cassette)
+        }
+        None => Gog::new(token),
+    }
+}
+
+// The test harness names each test's thread after the test
+fn cassette_path(dir: OsString) -> PathBuf {
+    let name = std::thread::current().name().unwrap_or("main").to_string();
+    PathBuf::from(dir).join(name + ".json")
 }
 
 #[test]
@@ -47,6 +78,22 @@ fn get_game_details() {
     gog.get_game_details(OWNED_GAME_ID).unwrap();
 }
 
+#[test]
+fn hide_reveal() {
+    let gog = get_gog();
+    for (_, res) in gog.hide_products(&[OWNED_GAME_ID]) {
+        assert!(res.unwrap().status().is_success());
+    }
+    assert!(gog
+        .hidden_products()
+        .unwrap()
+        .iter()
+        .any(|x| x.id == OWNED_GAME_ID));
+    for (_, res) in gog.reveal_products(&[OWNED_GAME_ID]) {
+        assert!(res.unwrap().status().is_success());
+    }
+}
+
 #[test]
 fn wishlist() {
     let gog = get_gog();
@@ -65,18 +112,114 @@ fn rm_wishlist() {
     gog.rm_wishlist(1096313866).unwrap();
 }
 
+#[test]
+fn wishlist_bulk() {
+    let gog = get_gog();
+    let added = gog
+        .add_wishlist_bulk(&[1096313866, 1096313866, 1452598881])
+        .unwrap();
+    assert!(added.len() <= 2);
+    let export = gog.export_wishlist().unwrap();
+    let export = wishlist::WishlistExport::from_json(export.to_json().unwrap()).unwrap();
+    assert!(export.ids().contains(&1096313866));
+    for (_, res) in gog.rm_wishlist_bulk(&[1096313866, 1452598881]).unwrap() {
+        res.unwrap();
+    }
+}
+
+#[test]
+fn wishlist_sharing() {
+    use gog::gog::status::WishlistSharing;
+    let gog = get_gog();
+    let sharing = gog.wishlist_sharing().unwrap();
+    gog.save_wishlist_sharing(WishlistSharing::Public).unwrap();
+    assert_eq!(gog.wishlist_sharing().unwrap(), WishlistSharing::Public);
+    gog.user_wishlist(gog.uid()).unwrap();
+    gog.save_wishlist_sharing(sharing).unwrap();
+}
+
+#[test]
+fn wishlist_watcher() {
+    let gog = get_gog();
+    let user = gog.get_user_data().unwrap();
+    let mut watcher = wishlist::WishlistWatcher::for_user(&user);
+    watcher.poll(&gog).unwrap();
+    let second = watcher.poll(&gog).unwrap();
+    assert!(second.is_empty());
+}
+
 #[test]
 fn product() {
-    let exp_game: Vec<String> = vec![
-        "downloads".to_string(),
-        "description".to_string(),
-        "screenshots".to_string(),
-        "related_products".to_string(),
+    use gog::gog::product::ProductExpand;
+    let exp_game = vec![
+        ProductExpand::Downloads,
+        ProductExpand::Description,
+        ProductExpand::Screenshots,
+        ProductExpand::RelatedProducts,
     ];
     let gog = get_gog();
     gog.product(vec![1452598881, 1096313866], exp_game).unwrap();
 }
 
+#[test]
+fn products_batched() {
+    let gog = get_gog();
+    let mut ids = gog.get_games().unwrap();
+    ids.push(-1);
+    let res = gog.products_batched(ids.clone(), vec![], 4);
+    assert_eq!(res.iter().map(|x| x.0).collect::<Vec<i64>>(), ids);
+    assert!(res.last().unwrap().1.is_err());
+}
+
+#[test]
+fn product_all_expanded() {
+    let gog = get_gog();
+    let products = gog
+        .product(vec![OWNED_GAME_ID], gog::gog::product::ProductExpand::all())
+        .unwrap();
+    assert!(!products[0].languages.is_empty());
+}
+
+#[test]
+fn tag_name_encoding() {
+    let gog = get_gog();
+    let id = gog.create_tag("My RPGs & co").unwrap();
+    assert!(gog.delete_tag(id).unwrap());
+}
+
+#[test]
+fn tags() {
+    let gog = get_gog();
+    let id = gog.create_tag("gog-rs test").unwrap();
+    let added = gog.add_tag_bulk(&[OWNED_GAME_ID, OWNED_GAME_ID], id);
+    assert_eq!(added.len(), 1);
+    assert!(gog.tags().unwrap().iter().any(|x| x.tag_id() == Some(id)));
+    let renamed = gog.rename_tag(id, "gog-rs test renamed").unwrap();
+    assert_eq!(gog.products_with_tag(renamed).unwrap(), vec![OWNED_GAME_ID]);
+    gog.rm_tag_bulk(&[OWNED_GAME_ID], renamed);
+    assert!(gog.delete_tag(renamed).unwrap());
+}
+
+#[test]
+fn tag_sync_plan() {
+    use gog::tags::*;
+    use std::collections::BTreeMap;
+    let sync = TagSync::from_json(r#"{"RPG": [1, 2], "Shooter": [3]}"#).unwrap();
+    let mut current = BTreeMap::new();
+    current.insert(
+        "RPG".to_string(),
+        CurrentTag {
+            id: 10,
+            products: [2, 4].into_iter().collect(),
+        },
+    );
+    let plan = sync.plan_against(&current);
+    assert_eq!(
+        plan.to_string(),
+        "+ RPG 1\n- RPG 4\n+ tag Shooter\n+ Shooter 3\n"
+    );
+}
+
 #[test]
 fn game_ratings() {
     let gog = get_gog();
@@ -97,6 +240,23 @@ fn filtered_all() {
         .unwrap();
 }
 
+#[test]
+fn filtered_iter() {
+    let gog = get_gog();
+    let mut products = gog
+        .filtered_products_iter(FilterParams::new().media_type(1))
+        .unwrap();
+    assert!(products.total_pages() >= 1);
+    let first = products.next().unwrap().unwrap();
+    let unowned = gog
+        .products_iter(FilterParams::new().search("Stellaris"))
+        .unwrap();
+    assert!(unowned.total_products() > 0);
+    let first_few: Vec<_> = unowned.take(5).collect::<Result<_>>().unwrap();
+    assert!(!first_few.is_empty());
+    assert!(first.id > 0);
+}
+
 #[test]
 fn filtered_os() {
     let gog = get_gog();
@@ -111,6 +271,50 @@ fn download() {
     gog.download_game(details.downloads.linux.unwrap());
 }
 
+#[test]
+fn download_extras() {
+    let gog = get_gog();
+    let details = gog.get_game_details(OWNED_GAME_ID).unwrap();
+    let manuals: Vec<Extra> = details
+        .extras
+        .into_iter()
+        .filter(|x| x.kind() == ExtraKind::Manual)
+        .collect();
+    for res in gog.download_extras(manuals) {
+        res.unwrap();
+    }
+}
+
+#[test]
+fn extra_kind() {
+    assert_eq!(ExtraKind::from("soundtrack"), ExtraKind::Soundtrack);
+    assert_eq!(ExtraKind::from("manuals"), ExtraKind::Manual);
+    assert_eq!(ExtraKind::from("wallpapers"), ExtraKind::Wallpapers);
+    assert_eq!(
+        ExtraKind::from("making of"),
+        ExtraKind::Other("making of".to_string())
+    );
+}
+
+#[test]
+fn download_size() {
+    let gog = get_gog();
+    let details = gog.get_game_details(OWNED_GAME_ID).unwrap();
+    let linux = details.downloads.linux.as_ref().unwrap();
+    assert!(gog.download_size(&linux[0].manual_url).unwrap() > 0);
+    assert!(details.total_size(Linux, "en") > 0);
+}
+
+#[test]
+fn parse_sizes_and_dates() {
+    assert_eq!(parse_size("1.5 GB"), Some(1610612736));
+    assert_eq!(parse_size("512 KB"), Some(524288));
+    assert_eq!(parse_size("big"), None);
+    let date = parse_date("2019-05-06").unwrap();
+    assert_eq!((date.year(), date.day()), (2019, 6));
+    assert_eq!(parse_date(""), None);
+}
+
 #[test]
 fn filtered_search() {
     let gog = get_gog();
@@ -118,6 +322,33 @@ fn filtered_search() {
         .unwrap();
 }
 
+#[test]
+fn filtered_sorted() {
+    let gog = get_gog();
+    let params = FilterParams::new()
+        .os(Linux)
+        .sort(SortOrder::Title)
+        .search("Beneath a Steel Sky");
+    gog.get_filtered_products(params).unwrap();
+}
+
+#[test]
+fn filter_query() {
+    let params = FilterParams::new().search("My RPGs & co").page(2);
+    assert_eq!(
+        params.query(FilterEndpoint::Store).unwrap(),
+        "?search=My+RPGs+%26+co&page=2&mediaType=1"
+    );
+    assert!(FilterParams::new()
+        .tag(1)
+        .query(FilterEndpoint::Store)
+        .is_err());
+    assert!(FilterParams::new()
+        .price_range(20, 10)
+        .query(FilterEndpoint::Store)
+        .is_err());
+}
+
 #[test]
 fn filtered_unowned() {
     let gog = get_gog();
@@ -125,6 +356,48 @@ fn filtered_unowned() {
         .unwrap();
 }
 
+#[test]
+fn catalog() {
+    let gog = get_gog();
+    let user = gog.get_user_data().unwrap();
+    let mut query = catalog::CatalogQuery::for_user(&user);
+    query.systems = vec![Linux];
+    query.discounted = true;
+    query.order = Some(catalog::CatalogOrder::Discount);
+    let page = gog.catalog(&query).unwrap();
+    assert!(page.products.len() <= 48);
+}
+
+#[test]
+fn catalog_query_default() {
+    let query = catalog::CatalogQuery {
+        search: Some("witcher".to_string()),
+        ..Default::default()
+    };
+    assert!(query.params().is_ok());
+}
+
+#[test]
+fn product_price() {
+    let gog = get_gog();
+    let user = gog.get_user_data().unwrap();
+    let mut history = prices::PriceHistory::new();
+    let price = gog.product_price_for_user(1096313866, &user).unwrap();
+    history.record(price);
+    assert!(history
+        .lowest(1096313866, user.selected_currency.code)
+        .is_some());
+}
+
+#[test]
+fn orders() {
+    let gog = get_gog();
+    let orders = gog.orders().unwrap();
+    let csv = orders::to_csv(&orders);
+    assert!(csv.starts_with("order_id,"));
+    orders::to_json(&orders).unwrap();
+}
+
 #[test]
 fn connect_status() {
     let gog = get_gog();
@@ -145,6 +418,341 @@ fn friends() {
     gog.friends().unwrap();
 }
 
+#[test]
+fn cache() {
+    use gog::cache::*;
+    use std::time::Duration;
+    let cache = Cache::new(std::env::temp_dir().join("gog-cache-test"))
+        .ttl("/products/1", Duration::from_secs(5));
+    assert_eq!(
+        cache.ttl_for("/products/1?expand=dlcs"),
+        Duration::from_secs(5)
+    );
+    assert_eq!(
+        cache.ttl_for("/products/2"),
+        Duration::from_secs(24 * 60 * 60)
+    );
+    assert_eq!(cache.ttl_for("/userData.json"), Duration::from_secs(0));
+    let entry = CacheEntry::new(
+        "https://api.gog.com/products/1",
+        None,
+        None,
+        "{}".to_string(),
+    );
+    cache.put("1", &entry).unwrap();
+    assert_eq!(cache.get("1", &entry.url).unwrap().body, "{}");
+    assert!(cache.get("2", &entry.url).is_none());
+    assert!(entry.is_fresh(Duration::from_secs(5)));
+    assert!(!entry.is_fresh(Duration::from_secs(0)));
+    cache.clear().unwrap();
+    assert!(cache.get("1", &entry.url).is_none());
+}
+
+#[test]
+fn cached_requests() {
+    let dir = std::env::temp_dir().join("gog-cached-requests-test");
+    let gog = get_gog().with_cache(gog::cache::Cache::new(&dir));
+    let first = gog.get_user_data().unwrap();
+    let second = gog.get_user_data().unwrap();
+    assert_eq!(first.user_id, second.user_id);
+    gog.product(vec![OWNED_GAME_ID], vec![]).unwrap();
+    gog.product(vec![OWNED_GAME_ID], vec![]).unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn fake_server_cache() {
+    let fake = gog::testing::FakeGog::start().unwrap();
+    fake.state().add_game(1, "Beneath a Steel Sky");
+    let dir = std::env::temp_dir().join("gog-fake-cache-test");
+    let cache = gog::cache::Cache::new(&dir).default_ttl(std::time::Duration::from_secs(3600));
+    assert!(!cache.caches("/account/tags/attach"));
+    let gog = fake.gog().with_cache(cache);
+    let tag = gog.create_tag("Played").unwrap();
+    assert!(gog.add_tag(1, tag).unwrap());
+    assert!(gog.rm_tag(1, tag).unwrap());
+    assert!(gog.add_tag(1, tag).unwrap());
+    gog.get_user_data().unwrap();
+    gog.get_user_data().unwrap();
+    let requests = fake.state().requests.clone();
+    let count = |path: &str| requests.iter().filter(|x| x.contains(path)).count();
+    assert_eq!(count("/account/tags/attach"), 2);
+    assert_eq!(count("/userData.json"), 1);
+    assert_eq!(fake.state().games[&1].tags.len(), 1);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn fake_server_record() {
+    let fake = gog::testing::FakeGog::start().unwrap();
+    let size = MAX_RECORDED_BODY + 1000;
+    let installer = {
+        let mut state = fake.state();
+        state.add_game(1, "Beneath a Steel Sky");
+        state.add_download(1, Linux, vec![7; size])
+    };
+    let path = std::env::temp_dir().join("gog-record-test.json");
+    let cassette = Cassette::record(&path, fake.state().user_id.clone());
+    let gog = fake.gog().with_cassette(cassette.clone());
+    let response = gog
+        .download_game(vec![installer.clone()])
+        .remove(0)
+        .unwrap();
+    assert!(response.url().path().starts_with("/files/"));
+    assert_eq!(response.bytes().unwrap().len(), size);
+    let recorded = cassette.interactions().pop().unwrap();
+    assert_eq!(recorded.bytes().unwrap().len(), MAX_RECORDED_BODY);
+    assert!(recorded.truncated);
+    // JSON is recorded whole, however long it is
+    let title = "A".repeat(MAX_RECORDED_BODY);
+    fake.state().games.get_mut(&1).unwrap().title = title.clone();
+    assert_eq!(gog.get_game_details(1).unwrap().title, title);
+    let recorded = cassette.interactions().pop().unwrap();
+    assert!(!recorded.truncated);
+    let replay = Cassette::replay(&path).unwrap();
+    let gog = fake.gog().with_cassette(replay);
+    assert_eq!(gog.get_game_details(1).unwrap().title, title);
+    assert!(gog.download_game(vec![installer]).remove(0).is_err());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn cassette_scrub() {
+    assert_eq!(
+        scrub(r#"{"access_token": "abc", "email":"someone@mail.com"}"#),
+        r#"{"access_token": "REDACTED", "email":"user@example.com"}"#
+    );
+    assert_eq!(
+        scrub("https://auth.gog.com/token?code=abc&grant_type=x"),
+        "https://auth.gog.com/token?code=REDACTED&grant_type=x"
+    );
+}
+
+#[test]
+fn cassette_replay() {
+    let path = std::env::temp_dir().join("gog-cassette-test.json");
+    let file = CassetteFile {
+        user_id: "1".to_string(),
+        interactions: vec![Interaction {
+            method: "GET".to_string(),
+            url: "https://embed.gog.com/user/data/games".to_string(),
+            range: None,
+            status: 200,
+            headers: vec![("content-type".to_string(), "application/json".to_string())],
+            body: r#"{"owned":[1,2]}"#.to_string(),
+            binary: false,
+            truncated: false,
+        }],
+    };
+    std::fs::write(&path, serde_json::to_string(&file).unwrap()).unwrap();
+    let cassette = Cassette::replay(&path).unwrap();
+    let gog = Gog::new(cassette.token()).with_cassette(cassette);
+    assert_eq!(gog.uid(), 1);
+    assert_eq!(gog.get_games().unwrap(), vec![1, 2]);
+    assert_eq!(gog.get_games().unwrap(), vec![1, 2]);
+    assert!(gog.wishlist().is_err());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn fake_server() {
+    use gog::testing::*;
+    let fake = FakeGog::start().unwrap();
+    let installer = {
+        let mut state = fake.state();
+        state.add_game(1, "Beneath a Steel Sky");
+        state.add_game(2, "Flight of the Amazon Queen");
+        let installer = state.add_download(1, Linux, (0..=255).collect());
+        let tag = state.add_tag("Favorite");
+        state.games.get_mut(&2).unwrap().tags.insert(tag);
+        state.wishlist.insert(3);
+        installer
+    };
+    let gog = fake.gog();
+    assert_eq!(gog.get_games().unwrap(), vec![1, 2]);
+    assert_eq!(gog.get_user_data().unwrap().user_id, fake.state().user_id);
+    let details = gog.get_game_details(1).unwrap();
+    assert_eq!(
+        details.downloads.linux.unwrap()[0].manual_url,
+        installer.manual_url
+    );
+    assert!(gog.get_game_details(4).is_err());
+    let found = gog
+        .get_filtered_products(FilterParams::from_one(Search("amazon".to_string())))
+        .unwrap();
+    assert_eq!(found.products.len(), 1);
+    assert_eq!(found.tags[0].count(), Some(1));
+    assert_eq!(gog.add_wishlist(4).unwrap().ids(), vec![3, 4]);
+    let tag = gog.create_tag("Played").unwrap();
+    assert!(gog.add_tag(1, tag).unwrap());
+    assert_eq!(gog.products_with_tag(tag).unwrap(), vec![1]);
+    assert!(gog.delete_tag(tag).unwrap());
+    assert_eq!(gog.download_size(&installer.manual_url).unwrap(), 256);
+    let response = gog.download_game(vec![installer]).remove(0).unwrap();
+    let url = response.url().to_string();
+    assert_eq!(response.bytes().unwrap().len(), 256);
+    assert_eq!(
+        gog.download_request_range(url, 16, 19).unwrap(),
+        vec![16, 17, 18, 19]
+    );
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn fake_server_filters() {
+    use gog::gog::status::WishlistSharing;
+    let fake = gog::testing::FakeGog::start().unwrap();
+    {
+        let mut state = fake.state();
+        state.add_game(1, "Beneath a Steel Sky");
+        state
+            .add_game(2, "Flight of the Amazon Queen")
+            .works_on
+            .linux = false;
+        state.add_store_product(3, "Lure of the Temptress");
+        state.wishlist.insert(3);
+    }
+    let gog = fake.gog();
+    let ids = |params: FilterParams| -> Vec<i64> {
+        gog.get_all_filtered_products(params)
+            .unwrap()
+            .iter()
+            .map(|x| x.id)
+            .collect()
+    };
+    assert_eq!(ids(FilterParams::new().os(Linux)), vec![1]);
+    assert_eq!(
+        ids(FilterParams::new().sort(SortOrder::DatePurchased)),
+        vec![2, 1]
+    );
+    let store = gog
+        .get_products(FilterParams::new().search("temptress"))
+        .unwrap();
+    assert_eq!(store.iter().map(|x| x.id).collect::<Vec<i64>>(), vec![3]);
+    let wishlisted = gog.wishlist_products().unwrap();
+    assert_eq!(
+        wishlisted.iter().map(|x| x.id).collect::<Vec<i64>>(),
+        vec![3]
+    );
+    assert_eq!(gog.user_wishlist(gog.uid()).unwrap().len(), 1);
+    assert_eq!(gog.wishlist_sharing().unwrap(), WishlistSharing::Public);
+    gog.save_wishlist_sharing(WishlistSharing::Private).unwrap();
+    assert!(matches!(
+        gog.user_wishlist(gog.uid()),
+        Err(Error(ErrorKind::NotAvailable, _))
+    ));
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn fake_server_refresh() {
+    let fake = gog::testing::FakeGog::start().unwrap();
+    let mut token = fake.token();
+    token.expires_in = 0;
+    let gog = Gog::new(token).with_base_url(fake.url());
+    gog.get_games().unwrap();
+    assert_eq!(gog.token.borrow().access_token, "access-1");
+    assert_eq!(fake.state().access_token, "access-1");
+}
+
+// Visits GOG's redirect uri from an auth url, the way the browser would after a login
+fn redirect_to(auth_url: &str, query: &'static str) {
+    let url = reqwest::Url::parse(auth_url).unwrap();
+    let redirect_uri = url
+        .query_pairs()
+        .find(|x| x.0 == "redirect_uri")
+        .unwrap()
+        .1
+        .to_string();
+    std::thread::spawn(move || {
+        reqwest::blocking::get(redirect_uri + query).unwrap();
+    });
+}
+
+#[test]
+fn browser_login() {
+    use gog::token::BrowserLogin;
+    use std::time::Duration;
+    let login = BrowserLogin::new().timeout(Duration::from_secs(10));
+    let (code, redirect_uri) = login
+        .wait_for_code(|url| {
+            assert!(url.starts_with("https://auth.gog.com/auth?client_id="));
+            redirect_to(url, "?code=abc")
+        })
+        .unwrap();
+    assert_eq!(code, "abc");
+    assert!(redirect_uri.starts_with("http://127.0.0.1:"));
+    let denied = login.wait_for_code(|url| redirect_to(url, "?error=access_denied"));
+    assert!(matches!(
+        denied.unwrap_err().kind(),
+        ErrorKind::LoginCancelled(x) if x == "access_denied"
+    ));
+    let short = BrowserLogin::new().timeout(Duration::from_millis(100));
+    assert!(matches!(
+        short.wait_for_code(|_| {}).unwrap_err().kind(),
+        ErrorKind::LoginTimeout
+    ));
+    let cancelled = BrowserLogin::new();
+    let handle = cancelled.cancel_handle();
+    let res = cancelled.wait_for_code(|_| handle.store(true, std::sync::atomic::Ordering::SeqCst));
+    assert!(matches!(
+        res.unwrap_err().kind(),
+        ErrorKind::LoginCancelled(_)
+    ));
+}
+
+#[test]
+fn library() {
+    let gog = get_gog();
+    let library = gog.library(library::DEFAULT_WORKERS).unwrap();
+    assert!(library.get(OWNED_GAME_ID).is_some());
+    let cached = library::Library::from_json(library.to_json().unwrap()).unwrap();
+    assert_eq!(cached.ids(), library.ids());
+}
+
+#[test]
+fn sync() {
+    let gog = get_gog();
+    let mut sync = sync::LibrarySync::new();
+    let events = sync.poll(&gog).unwrap();
+    assert!(events.contains(&sync::SyncEvent::GameAdded(OWNED_GAME_ID)));
+    assert!(sync.poll(&gog).unwrap().is_empty());
+}
+
+#[test]
+fn cd_keys() {
+    assert_eq!(
+        parse_cd_keys("ABCD-1234"),
+        vec![CdKey {
+            label: None,
+            key: "ABCD-1234".to_string()
+        }]
+    );
+    let keys =
+        parse_cd_keys("<span>Base game:</span><br>AAAA-1111<br><span>DLC:</span><br>BBBB-2222");
+    assert_eq!(keys.len(), 2);
+    assert_eq!(keys[1].label.as_deref(), Some("DLC"));
+    assert_eq!(keys[1].key, "BBBB-2222");
+}
+
+#[test]
+fn changelog() {
+    use gog::changelog::*;
+    let entries = parse_changelog(
+        "<h4>Version 1.10 (2020-05-06)</h4><ul><li>Fixed saves</li><li>Faster &amp; smaller</li></ul>\
+         <h4>Version 1.9</h4><ul><li>Initial release</li></ul>",
+    );
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].version.as_deref(), Some("1.10"));
+    assert_eq!(entries[0].date.unwrap().day(), 6);
+    assert_eq!(entries[0].changes[1], "Faster & smaller");
+    assert_eq!(changes_since(&entries, "1.9").len(), 1);
+    assert!(to_markdown(&entries).starts_with("## Version 1.10"));
+}
+
 #[test]
 fn extract_data() {
     let gog = get_gog();
@@ -152,6 +760,128 @@ fn extract_data() {
     assert!(gog.extract_data(details.downloads.linux.unwrap()).is_ok())
 }
 
+#[test]
+#[ignore]
+fn mirror() {
+    let gog = get_gog();
+    let mut library = gog.library(library::DEFAULT_WORKERS).unwrap();
+    library.games.retain(|x| x.id() == OWNED_GAME_ID);
+    let root = std::env::temp_dir().join("gog-mirror-test");
+    let mut mirror = mirror::Mirror::new(&root);
+    mirror.os = vec![Linux];
+    let report = mirror.run(&gog, &library).unwrap();
+    assert!(report.failed.is_empty());
+    let report = mirror.run(&gog, &library).unwrap();
+    assert!(report.downloaded.is_empty());
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn fake_server_library_hidden() {
+    let fake = gog::testing::FakeGog::start().unwrap();
+    {
+        let mut state = fake.state();
+        state.add_game(1, "Beneath a Steel Sky");
+        state.add_game(2, "Flight of the Amazon Queen");
+    }
+    let gog = fake.gog();
+    gog.hide_product(1).unwrap();
+    let library = gog.library(2).unwrap();
+    let mut ids = library.ids();
+    ids.sort();
+    assert_eq!(ids, vec![1, 2]);
+    let hidden: Vec<i64> = library
+        .games
+        .iter()
+        .filter(|x| x.hidden())
+        .map(|x| x.id())
+        .collect();
+    assert_eq!(hidden, vec![1]);
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn fake_server_mirror() {
+    let fake = gog::testing::FakeGog::start().unwrap();
+    let installer = {
+        let mut state = fake.state();
+        state.add_game(1, "Beneath a Steel Sky");
+        state.add_download(1, Linux, vec![1; 64])
+    };
+    let gog = fake.gog();
+    let library = gog.library(1).unwrap();
+    let root = std::env::temp_dir().join("gog-fake-mirror-test");
+    let mut mirror = mirror::Mirror::new(&root);
+    mirror.os = vec![Linux];
+    let report = mirror.run(&gog, &library).unwrap();
+    assert_eq!(report.downloaded.len(), 1);
+    fake.state().requests.clear();
+    let report = mirror.run(&gog, &library).unwrap();
+    assert!(report.downloaded.is_empty());
+    let requests = fake.state().requests.clone();
+    assert!(!requests
+        .iter()
+        .any(|x| x.starts_with("GET /files") && !x.ends_with(".xml")));
+    // Republished at the same size, so only GOG's checksum tells it apart
+    fake.state()
+        .files
+        .insert(installer.manual_url.clone(), vec![2; 64]);
+    let report = mirror.run(&gog, &library).unwrap();
+    assert_eq!(report.downloaded.len(), 1);
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn fake_server_mirror_keeps_files() {
+    let fake = gog::testing::FakeGog::start().unwrap();
+    {
+        let mut state = fake.state();
+        state.add_game(1, "Beneath a Steel Sky");
+        state.add_download(1, Linux, vec![1; 64]);
+        state.add_game(2, "Flight of the Amazon Queen");
+        state.add_download(2, Linux, vec![2; 64]);
+    }
+    let gog = fake.gog();
+    let root = std::env::temp_dir().join("gog-fake-mirror-keep-test");
+    let mut mirror = mirror::Mirror::new(&root);
+    mirror.os = vec![Linux];
+    let report = mirror.run(&gog, &gog.library(1).unwrap()).unwrap();
+    assert_eq!(report.downloaded.len(), 2);
+    gog.hide_product(1).unwrap();
+    let report = mirror.run(&gog, &gog.library(1).unwrap()).unwrap();
+    assert!(report.removed.is_empty());
+    // Games missing from the library and OSes that aren't mirrored leave files alone
+    let mut library = gog.library(1).unwrap();
+    library.games.retain(|x| x.id() == 2);
+    mirror.os = vec![Windows];
+    let report = mirror.run(&gog, &library).unwrap();
+    assert!(report.removed.is_empty());
+    assert_eq!(report.manifest.files.len(), 2);
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn fake_server_mirror_unsafe_path() {
+    let fake = gog::testing::FakeGog::start().unwrap();
+    {
+        let mut state = fake.state();
+        state.add_game(1, "Beneath a Steel Sky");
+        state.add_download(1, Linux, vec![1; 64]);
+        state.games.get_mut(&1).unwrap().slug = "..".to_string();
+    }
+    let gog = fake.gog();
+    let root = std::env::temp_dir().join("gog-fake-mirror-unsafe-test");
+    let mut mirror = mirror::Mirror::new(&root);
+    mirror.os = vec![Linux];
+    let report = mirror.run(&gog, &gog.library(1).unwrap()).unwrap();
+    assert!(report.downloaded.is_empty());
+    assert_eq!(report.failed.len(), 1);
+    std::fs::remove_dir_all(root).unwrap();
+}
+
 #[test]
 #[ignore]
 fn extract() {